@@ -0,0 +1,15 @@
+#version 430 core
+
+layout(location = 0) in vec3 position;
+layout(location = 1) in vec4 color;
+
+out vec4 vertexColor;
+
+// Transformation matrix passed from CPU
+uniform mat4 u_transform;
+
+void main()
+{
+    gl_Position = u_transform * vec4(position, 1.0);
+    vertexColor = color;
+}
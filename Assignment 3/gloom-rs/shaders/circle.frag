@@ -0,0 +1,12 @@
+#version 430 core
+
+uniform float uTime;   // seconds since start
+out vec4 color;
+
+void main()
+{
+    float r = 0.5 + 0.5 * sin(uTime * 0.8);
+    float g = 0.5 + 0.5 * sin(uTime * 0.8 + 2.094); // +120°
+    float b = 0.5 + 0.5 * sin(uTime * 0.8 + 4.188); // +240°
+    color = vec4(r, g, b, 1.0);
+}
@@ -0,0 +1,44 @@
+// Phong shading, shared by every fragment shader that lights a surface
+
+// Read from the MTL file of the model, and multiplied with the vertex color
+struct Material {
+    vec3 ambient;
+    vec3 diffuse;
+    vec3 specular;
+    float shininess;
+    float dissolve;
+};
+
+uniform vec3 u_lightPos;
+uniform vec3 u_viewPos;
+uniform Material u_material;
+
+// The lit color of a surface point with the given color and (normalized) normal
+vec3 phong(vec3 surfaceColor, vec3 norm, vec3 fragPos)
+{
+    vec3 emissiveColor = vec3(0.0, 0.0, 0.0); // Only here because of formula
+    vec3 ambientStrength  = u_material.ambient;
+    vec3 diffuseStrength  = u_material.diffuse;
+    vec3 specularStrength = u_material.specular;
+    float shininess = u_material.shininess;
+
+    // Emissive
+    vec3 emissive = emissiveColor;
+
+    // Ambient
+    vec3 ambient = ambientStrength * surfaceColor;
+
+    // Diffuse
+    vec3 lightDir = normalize(u_lightPos - fragPos);
+    float diff = max(dot(norm, lightDir), 0.0);
+    vec3 diffuse = diffuseStrength * diff * surfaceColor;
+
+    // Specular
+    vec3 viewDir = normalize(u_viewPos - fragPos);
+    vec3 reflectDir = reflect(-lightDir, norm);
+    float spec = pow(max(dot(viewDir, reflectDir), 0.0), shininess);
+    vec3 specular = specularStrength * spec * vec3(1.0); // white highlight
+
+    // Final color: I=Ie+Ia+Id+Is
+    return emissive + ambient + diffuse + specular;
+}
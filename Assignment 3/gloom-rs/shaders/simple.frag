@@ -1,57 +1,41 @@
 #version 430 core
 
-//in vec4 vertexColor;
-//in vec3 vertexNormal;
-
-//out vec4 color;
-
-//void main()
-//{
-//    //color = vec4(vertexNormal, 1.0);
-
-//    vec3 lightDirection = normalize(vec3(0.8, -0.5, 0.6));
-//    vec3 litColor = vertexColor.rgb * (max(0.0, dot(vertexNormal, -lightDirection)));
-//    color = vec4(litColor, 1.0);
-//}
-
 // Phong shading
 
+#include "lighting.glsl"
+
 in vec4 vertexColor;
 in vec3 vertexNormal;
 in vec3 fragPos;
+in vec2 vertexTexcoord;
+in vec4 vertexTangent;
 
 out vec4 color;
 
-uniform vec3 u_lightPos;
-uniform vec3 u_viewPos;
+uniform bool u_hasDiffuseTexture;
+uniform sampler2D u_diffuseTexture;
+
+// Tangent space normals, only used when the mesh has tangents
+uniform bool u_hasNormalMap;
+uniform sampler2D u_normalMap;
 
 void main()
 {
-    vec3 emissiveColor = vec3(0.0, 0.0, 0.0); // Only here because of formula
-    vec3 ambientStrength  = vec3(0.2);
-    vec3 diffuseStrength  = vec3(0.7);
-    vec3 specularStrength = vec3(0.5);
-    float shininess = 32.0;
-
-    // Emissive
-    vec3 emissive = emissiveColor;
-
-    // Ambient
-    vec3 ambient = ambientStrength * vertexColor.rgb;
+    vec4 surfaceColor = vertexColor;
+    if (u_hasDiffuseTexture) {
+        surfaceColor *= texture(u_diffuseTexture, vertexTexcoord);
+    }
 
-    // Diffuse
     vec3 norm = normalize(vertexNormal);
-    vec3 lightDir = normalize(u_lightPos - fragPos);
-    float diff = max(dot(norm, lightDir), 0.0);
-    vec3 diffuse = diffuseStrength * diff * vertexColor.rgb;
-
-    // Specular
-    vec3 viewDir = normalize(u_viewPos - fragPos);
-    vec3 reflectDir = reflect(-lightDir, norm);  
-    float spec = pow(max(dot(viewDir, reflectDir), 0.0), shininess);
-    vec3 specular = specularStrength * spec * vec3(1.0); // white highlight
-
-    // Final color: I=Ie​+Ia​+Id​+Is​
-    vec3 result = emissive + ambient + diffuse + specular;
-    color = vec4(result, 1.0);
-}
\ No newline at end of file
+    if (u_hasNormalMap) {
+        // Like Mesh::generate_tangents expects: the bitangent is rebuilt from the interpolated
+        // normal and tangent instead of being interpolated itself
+        vec3 tangentDir = normalize(vertexTangent.xyz - norm * dot(norm, vertexTangent.xyz));
+        vec3 bitangentDir = vertexTangent.w * cross(norm, tangentDir);
+        vec3 mapped = texture(u_normalMap, vertexTexcoord).rgb * 2.0 - 1.0;
+        norm = normalize(mat3(tangentDir, bitangentDir, norm) * mapped);
+    }
+
+    vec3 result = phong(surfaceColor.rgb, norm, fragPos);
+    color = vec4(result, surfaceColor.a * u_material.dissolve);
+}
@@ -1,12 +1,17 @@
 #version 430 core
 
+// Attribute locations match VERTEX_ATTRIBUTES in mesh.rs
 layout(location = 0) in vec3 position;
 layout(location = 1) in vec4 color;
 layout(location = 2) in vec3 normal;
+layout(location = 3) in vec2 texcoord;
+layout(location = 4) in vec4 tangent;
 
 out vec4 vertexColor;
 out vec3 vertexNormal;
 out vec3 fragPos;
+out vec2 vertexTexcoord;
+out vec4 vertexTangent;
 
 uniform mat4 u_model;
 uniform mat4 u_model_view_projection;
@@ -22,4 +27,9 @@ void main()
 
     fragPos = vec3(u_model * vec4(position, 1.0));
 
+    vertexTexcoord = texcoord;
+
+    // The handedness in w is passed on untouched
+    vertexTangent = vec4(normalize(croppedModel * tangent.xyz), tangent.w);
+
 }
\ No newline at end of file
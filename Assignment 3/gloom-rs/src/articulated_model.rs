@@ -0,0 +1,107 @@
+extern crate nalgebra_glm as glm;
+
+use serde::{Serialize, Deserialize};
+
+use crate::mesh::{Mesh, Model};
+use crate::scene_file::{Animation, white, is_white, is_zero};
+
+// An articulated model is an OBJ file whose objects are the moving parts of a single vehicle. A
+// small RON manifest says which object plays which role, where each part pivots and what color
+// it is painted, so any vehicle can be dropped into a scene without writing Rust code for it:
+//
+//     #![enable(implicit_some)]
+//     (
+//         path: "./resources/helicopter.obj",
+//         parts: [
+//             (object: "Body_body", role: "body", color: (0.3, 0.3, 0.3, 1.0)),
+//             (object: "Tail_Rotor_tail_rotor", role: "tail_rotor", pivot: (0.35, 2.3, 10.4),
+//              animation: Spin(rates: (15.0, 0.0, 0.0))),
+//         ],
+//     )
+//
+// Parts are attached directly to the model's root node, unless they name the role of an
+// earlier part as their parent.
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ModelManifest {
+    pub path  : String,
+    pub parts : Vec<PartManifest>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PartManifest {
+    pub object : String,
+    pub role   : String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent : Option<String>,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub pivot : [f32; 3],
+    #[serde(default = "white", skip_serializing_if = "is_white")]
+    pub color : [f32; 4],
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub animation : Option<Animation>,
+}
+
+pub struct Part {
+    pub role      : String,
+    pub parent    : Option<usize>,   // Index of the part I am attached to, if not the model's root
+    pub mesh      : Mesh,
+    pub pivot     : glm::Vec3,
+    pub animation : Option<Animation>,
+}
+
+pub struct ArticulatedModel {
+    pub parts : Vec<Part>,
+}
+
+impl ArticulatedModel {
+    pub fn load(manifest_path: &str) -> Result<ArticulatedModel, String> {
+        let source = std::fs::read_to_string(manifest_path)
+            .map_err(|e| format!("Failed to read model manifest {}: {}", manifest_path, e))?;
+        let manifest: ModelManifest = ron::from_str(&source)
+            .map_err(|e| format!("Failed to parse model manifest {}: {}", manifest_path, e))?;
+        ArticulatedModel::from_manifest(&manifest)
+    }
+
+    pub fn from_manifest(manifest: &ModelManifest) -> Result<ArticulatedModel, String> {
+        let model = Model::load(&manifest.path)?;
+        let mut parts: Vec<Part> = Vec::with_capacity(manifest.parts.len());
+        for part in &manifest.parts {
+            let mut mesh = model.get(&part.object)
+                .ok_or_else(|| format!("{} has no object named {} for the {}", manifest.path, part.object, part.role))?
+                .clone();
+            mesh.set_color(part.color);
+
+            let parent = match &part.parent {
+                Some(role) => Some(parts.iter().position(|p| &p.role == role)
+                    .ok_or_else(|| format!("The parent {} of {} must be listed before it", role, part.role))?),
+                None => None,
+            };
+
+            parts.push(Part {
+                role      : part.role.clone(),
+                parent,
+                mesh,
+                pivot     : part.pivot.into(),
+                animation : part.animation.clone(),
+            });
+        }
+        Ok(ArticulatedModel { parts })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn helicopter_manifest_parses() {
+        let source = std::fs::read_to_string("./resources/helicopter.ron").unwrap();
+        let manifest: ModelManifest = ron::from_str(&source).unwrap();
+        let roles: Vec<&str> = manifest.parts.iter().map(|p| p.role.as_str()).collect();
+        assert_eq!(roles, ["body", "door", "main_rotor", "tail_rotor"]);
+        assert_eq!(manifest.parts[3].pivot, [0.35, 2.3, 10.4]);
+        assert_eq!(manifest.parts[0].animation, None);
+    }
+}
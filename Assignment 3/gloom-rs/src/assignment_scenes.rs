@@ -0,0 +1,203 @@
+use crate::gpu::{Buffer, VertexArray};
+use crate::shader::{Shader, ShaderBuilder};
+use crate::vertex::{ComponentType, VertexBuffer, VertexLayout};
+
+// The final scenes of assignments 1 and 2, rendered headless so that tests/golden.rs can check
+// them like the lunar scene. The shapes, camera and shaders are copied from those assignments, in
+// shaders/circle.* and shaders/billboards.*, and drawn with the objects of this crate.
+
+// Loads shaders/<name>.vert and shaders/<name>.frag
+unsafe fn load_shader(name: &str) -> Result<Shader, String> {
+    ShaderBuilder::new()
+        .attach_file(&format!("./shaders/{}.vert", name))
+        .and_then(|builder| builder.attach_file(&format!("./shaders/{}.frag", name)))
+        .and_then(|builder| builder.link())
+        .map_err(|e| e.to_string())
+}
+
+// Uploads vertices into a new VAO, with an index buffer unless `indices` is empty
+unsafe fn upload(vertices: VertexBuffer, indices: &[u32]) -> VertexArray {
+    let mut vao = VertexArray::new();
+    vao.bind();
+    vao.attach(vertices.upload());
+    if !indices.is_empty() {
+        vao.attach(Buffer::with_data(gl::ELEMENT_ARRAY_BUFFER, indices));
+    }
+    gl::BindVertexArray(0);
+    vao
+}
+
+
+// Assignment 1, task 3 d): a circle drawn as a triangle fan, slowly changing color
+
+const NUM_SEGMENTS: usize = 100;
+
+// A circle of radius 0.5 around (0,0), as a triangle fan: the center, then the rim all the way
+// around
+fn circle_vertices() -> Vec<f32> {
+    let mut vertices: Vec<f32> = Vec::with_capacity((NUM_SEGMENTS + 2) * 3);
+    vertices.extend_from_slice(&[0.0, 0.0, 0.0]);
+    for i in 0..=NUM_SEGMENTS {
+        let theta = 2.0 * std::f32::consts::PI * (i as f32) / (NUM_SEGMENTS as f32);
+        vertices.extend_from_slice(&[theta.cos() * 0.5, theta.sin() * 0.5, 0.0]);
+    }
+    vertices
+}
+
+pub struct Circle {
+    shader : Shader,
+    vao    : VertexArray,
+}
+
+impl Circle {
+    pub unsafe fn new() -> Result<Circle, String> {
+        let layout = VertexLayout::new().with("position", 0, 3, ComponentType::Float, false);
+        let vertices = VertexBuffer::builder(layout, NUM_SEGMENTS + 2)
+            .attribute("position", &circle_vertices())
+            .build()?;
+
+        Ok(Circle {
+            shader : load_shader("circle")?,
+            vao    : upload(vertices, &[]),
+        })
+    }
+
+    pub unsafe fn draw(&self, elapsed: f32) {
+        gl::ClearColor(0.035, 0.046, 0.078, 1.0); // night sky
+        gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+
+        self.shader.activate();
+        self.shader.set_f32("uTime", elapsed);
+        self.vao.bind();
+        gl::DrawArrays(gl::TRIANGLE_FAN, 0, (NUM_SEGMENTS + 2) as i32);
+        gl::BindVertexArray(0);
+    }
+}
+
+
+// Assignment 2, task 5 c): three overlapping translucent triangles, with three billboards
+// floating behind them
+
+const TRIANGLE_VERTICES: [f32; 27] = [
+    -0.6,  -0.4,  0.1,   0.6,  -0.4,  0.1,   0.0, 0.6,  0.1,   // Furthest, largest
+    -0.45, -0.45, 0.2,   0.45, -0.45, 0.2,   0.0, 0.45, 0.2,   // Middle
+    -0.3,  -0.5,  0.3,   0.3,  -0.5,  0.3,   0.0, 0.3,  0.3,   // Closest, smallest
+];
+
+const TRIANGLE_INDICES: [u32; 9] = [0, 1, 2,  3, 4, 5,  6, 7, 8];
+
+const TRIANGLE_COLORS: [f32; 36] = [
+    0.98, 0.95, 0.70, 0.6,   0.98, 0.95, 0.70, 0.6,   0.98, 0.95, 0.70, 0.6,   // Light yellow
+    0.96, 0.72, 0.72, 0.6,   0.96, 0.72, 0.72, 0.6,   0.96, 0.72, 0.72, 0.6,   // Light red
+    0.68, 0.85, 0.90, 0.6,   0.68, 0.85, 0.90, 0.6,   0.68, 0.85, 0.90, 0.6,   // Light blue
+];
+
+// Where the triangles are placed in the world
+fn triangles_model() -> glm::Mat4 {
+    glm::translate(&glm::identity(), &glm::vec3(0.0, 0.0, -2.0))
+}
+
+// The camera of assignment 2, which turns by yaw and pitch before moving
+fn view_matrix(cam_pos: &glm::Vec3, cam_yaw: f32, cam_pitch: f32) -> glm::Mat4 {
+    let mut view: glm::Mat4 = glm::identity();
+    view = glm::rotate(&view, -cam_yaw, &glm::vec3(0.0, 1.0, 0.0));
+    view = glm::rotate(&view, -cam_pitch, &glm::vec3(1.0, 0.0, 0.0));
+    glm::translate(&view, &(-cam_pos))
+}
+
+fn projection_matrix(aspect_ratio: f32) -> glm::Mat4 {
+    glm::perspective(aspect_ratio, std::f32::consts::FRAC_PI_4, 1.0, 100.0)
+}
+
+// A white, slightly translucent quad of the given size centered at origin in the XY plane, as
+// (vertices, colors, indices)
+fn billboard(size: f32) -> (Vec<f32>, Vec<f32>, Vec<u32>) {
+    let s = size * 0.5;
+    let vertices = vec![-s, -s, 0.0,   s, -s, 0.0,   s, s, 0.0,   -s, s, 0.0];
+    let colors = [1.0, 1.0, 1.0, 0.9].repeat(4);
+    let indices = vec![0, 1, 2,  0, 2, 3];
+    (vertices, colors, indices)
+}
+
+// Where the three billboards float at a point in time, and how large they are
+fn billboard_instances(elapsed: f32) -> [(glm::Vec3, f32); 3] {
+    let y1 = (elapsed * 1.3).sin() * 0.2;
+    let y2 = (elapsed * 1.6 + 1.2).sin() * 0.2;
+    let y3 = (elapsed * 1.1 + 2.1).sin() * 0.2;
+    [
+        (glm::vec3( 0.1,  0.1 + y1, -3.6), 0.9),
+        (glm::vec3( 0.0,  0.4 + y2, -4.0), 1.0),
+        (glm::vec3(-0.1, -0.1 + y3, -3.8), 0.8),
+    ]
+}
+
+// The rotation which turns the billboards towards the camera: the 3x3 rotation of the view,
+// transposed
+fn billboard_rotation(view: &glm::Mat4) -> glm::Mat4 {
+    let mut rotation: glm::Mat4 = glm::identity();
+    for row in 0..3 {
+        for column in 0..3 {
+            rotation[(row, column)] = view[(column, row)];
+        }
+    }
+    rotation
+}
+
+pub struct Billboards {
+    shader    : Shader,
+    triangles : VertexArray,
+    billboard : VertexArray,
+}
+
+fn colored_layout() -> VertexLayout {
+    VertexLayout::new()
+        .with("position", 0, 3, ComponentType::Float, false)
+        .with("color", 1, 4, ComponentType::Float, false)
+}
+
+impl Billboards {
+    pub unsafe fn new() -> Result<Billboards, String> {
+        let triangles = VertexBuffer::builder(colored_layout(), TRIANGLE_VERTICES.len() / 3)
+            .attribute("position", &TRIANGLE_VERTICES)
+            .attribute("color", &TRIANGLE_COLORS)
+            .build()?;
+
+        let (vertices, colors, indices) = billboard(0.3);
+        let billboard = VertexBuffer::builder(colored_layout(), vertices.len() / 3)
+            .attribute("position", &vertices)
+            .attribute("color", &colors)
+            .build()?;
+
+        Ok(Billboards {
+            shader    : load_shader("billboards")?,
+            triangles : upload(triangles, &TRIANGLE_INDICES),
+            billboard : upload(billboard, &indices),
+        })
+    }
+
+    // Seen from the side, as the billboards are hidden behind the triangles from where the camera
+    // of the assignment starts out
+    pub unsafe fn draw(&self, elapsed: f32, aspect_ratio: f32) {
+        let view = view_matrix(&glm::vec3(1.5, 0.3, 0.0), 0.45, -0.05);
+        let projection = projection_matrix(aspect_ratio);
+
+        gl::ClearColor(0.035, 0.046, 0.078, 1.0); // night sky
+        gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+
+        self.shader.activate();
+        self.shader.set_mat4("u_transform", &(projection * view * triangles_model()));
+        self.triangles.bind();
+        gl::DrawElements(gl::TRIANGLES, TRIANGLE_INDICES.len() as i32, gl::UNSIGNED_INT, std::ptr::null());
+
+        let rotation = billboard_rotation(&view);
+        gl::Disable(gl::CULL_FACE);
+        self.billboard.bind();
+        for (position, scale) in billboard_instances(elapsed).iter() {
+            let model = glm::translate(&glm::identity(), position) * rotation * glm::scaling(&glm::vec3(*scale, *scale, 1.0));
+            self.shader.set_mat4("u_transform", &(projection * view * model));
+            gl::DrawElements(gl::TRIANGLES, 6, gl::UNSIGNED_INT, std::ptr::null());
+        }
+        gl::BindVertexArray(0);
+        gl::Enable(gl::CULL_FACE);
+    }
+}
@@ -0,0 +1,168 @@
+use std::ffi::CStr;
+
+use crate::util::get_gl_string;
+
+// What the driver supports, queried once at startup. Features that are not available everywhere
+// check here first and fall back to something simpler, instead of failing on some machines.
+
+// From EXT/ARB_texture_filter_anisotropic, which the generated bindings do not include
+pub const MAX_TEXTURE_MAX_ANISOTROPY : gl::types::GLenum = 0x84FF;
+pub const TEXTURE_MAX_ANISOTROPY     : gl::types::GLenum = 0x84FE;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GlCapabilities {
+    pub vendor                          : String,
+    pub renderer                        : String,
+    pub version                         : String,
+    pub glsl_version                    : String,
+    pub major_version                   : i32,
+    pub minor_version                   : i32,
+    pub max_texture_size                : i32,
+    pub max_texture_image_units         : i32,
+    pub max_vertex_attribs              : i32,
+    pub max_vertex_uniform_components   : i32,
+    pub max_fragment_uniform_components : i32,
+    pub max_uniform_block_size          : i32,
+    pub max_samples                     : i32,
+    pub max_anisotropy                  : Option<f32>,   // None without anisotropic filtering
+    pub debug_output                    : bool,          // Whether gl_debug can be used
+    pub compute_shaders                 : bool,
+    pub extensions                      : Vec<String>,   // Sorted
+}
+
+unsafe fn get_integer(name: gl::types::GLenum) -> i32 {
+    let mut value = 0;
+    gl::GetIntegerv(name, &mut value);
+    value
+}
+
+impl GlCapabilities {
+    // Queries the context current on this thread
+    pub unsafe fn query() -> GlCapabilities {
+        let mut extensions: Vec<String> = (0..get_integer(gl::NUM_EXTENSIONS).max(0) as u32)
+            .map(|i| CStr::from_ptr(gl::GetStringi(gl::EXTENSIONS, i) as *const libc::c_char).to_string_lossy().to_string())
+            .collect();
+        extensions.sort();
+
+        let mut capabilities = GlCapabilities {
+            vendor                          : get_gl_string(gl::VENDOR),
+            renderer                        : get_gl_string(gl::RENDERER),
+            version                         : get_gl_string(gl::VERSION),
+            glsl_version                    : get_gl_string(gl::SHADING_LANGUAGE_VERSION),
+            major_version                   : get_integer(gl::MAJOR_VERSION),
+            minor_version                   : get_integer(gl::MINOR_VERSION),
+            max_texture_size                : get_integer(gl::MAX_TEXTURE_SIZE),
+            max_texture_image_units         : get_integer(gl::MAX_TEXTURE_IMAGE_UNITS),
+            max_vertex_attribs              : get_integer(gl::MAX_VERTEX_ATTRIBS),
+            max_vertex_uniform_components   : get_integer(gl::MAX_VERTEX_UNIFORM_COMPONENTS),
+            max_fragment_uniform_components : get_integer(gl::MAX_FRAGMENT_UNIFORM_COMPONENTS),
+            max_uniform_block_size          : get_integer(gl::MAX_UNIFORM_BLOCK_SIZE),
+            max_samples                     : get_integer(gl::MAX_SAMPLES),
+            extensions,
+            ..GlCapabilities::default()
+        };
+
+        capabilities.debug_output = capabilities.supports_version(4, 3) || capabilities.has_extension("GL_KHR_debug");
+        capabilities.compute_shaders = capabilities.supports_version(4, 3) || capabilities.has_extension("GL_ARB_compute_shader");
+        // Only asked for when supported, as the debug output would complain about the enum otherwise
+        if capabilities.supports_version(4, 6)
+            || capabilities.has_extension("GL_ARB_texture_filter_anisotropic")
+            || capabilities.has_extension("GL_EXT_texture_filter_anisotropic")
+        {
+            let mut max_anisotropy = 1.0;
+            gl::GetFloatv(MAX_TEXTURE_MAX_ANISOTROPY, &mut max_anisotropy);
+            capabilities.max_anisotropy = Some(max_anisotropy);
+        }
+        capabilities
+    }
+
+    pub fn supports_version(&self, major: i32, minor: i32) -> bool {
+        (self.major_version, self.minor_version) >= (major, minor)
+    }
+
+    pub fn has_extension(&self, name: &str) -> bool {
+        self.extensions.binary_search_by(|e| e.as_str().cmp(name)).is_ok()
+    }
+
+    pub fn to_json(&self) -> String {
+        let fields = [
+            ("vendor", json_string(&self.vendor)),
+            ("renderer", json_string(&self.renderer)),
+            ("version", json_string(&self.version)),
+            ("glsl_version", json_string(&self.glsl_version)),
+            ("major_version", self.major_version.to_string()),
+            ("minor_version", self.minor_version.to_string()),
+            ("max_texture_size", self.max_texture_size.to_string()),
+            ("max_texture_image_units", self.max_texture_image_units.to_string()),
+            ("max_vertex_attribs", self.max_vertex_attribs.to_string()),
+            ("max_vertex_uniform_components", self.max_vertex_uniform_components.to_string()),
+            ("max_fragment_uniform_components", self.max_fragment_uniform_components.to_string()),
+            ("max_uniform_block_size", self.max_uniform_block_size.to_string()),
+            ("max_samples", self.max_samples.to_string()),
+            ("max_anisotropy", self.max_anisotropy.map_or("null".to_string(), |a| format!("{:?}", a))),
+            ("debug_output", self.debug_output.to_string()),
+            ("compute_shaders", self.compute_shaders.to_string()),
+            ("extensions", if self.extensions.is_empty() {
+                "[]".to_string()
+            } else {
+                let extensions: Vec<String> = self.extensions.iter().map(|e| format!("    {}", json_string(e))).collect();
+                format!("[\n{}\n  ]", extensions.join(",\n"))
+            }),
+        ];
+        let fields: Vec<String> = fields.iter().map(|(name, value)| format!("  \"{}\": {}", name, value)).collect();
+        format!("{{\n{}\n}}", fields.join(",\n"))
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut json = String::with_capacity(s.len() + 2);
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"'  => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn versions_and_extensions_are_looked_up() {
+        let capabilities = GlCapabilities {
+            major_version : 4,
+            minor_version : 5,
+            extensions    : vec!["GL_ARB_bindless_texture".to_string(), "GL_KHR_debug".to_string()],
+            ..GlCapabilities::default()
+        };
+        assert!(capabilities.supports_version(3, 3));
+        assert!(capabilities.supports_version(4, 5));
+        assert!(!capabilities.supports_version(4, 6));
+        assert!(capabilities.has_extension("GL_KHR_debug"));
+        assert!(!capabilities.has_extension("GL_EXT_texture_filter_anisotropic"));
+    }
+
+    #[test]
+    fn json_is_escaped() {
+        let capabilities = GlCapabilities {
+            renderer       : "llvmpipe \"LLVM\"\\15".to_string(),
+            max_anisotropy : Some(16.0),
+            extensions     : vec!["GL_KHR_debug".to_string()],
+            ..GlCapabilities::default()
+        };
+        let json = capabilities.to_json();
+        assert!(json.starts_with("{\n  \"vendor\": \"\",\n  \"renderer\": \"llvmpipe \\\"LLVM\\\"\\\\15\",\n"), "{}", json);
+        assert!(json.contains("\"max_anisotropy\": 16.0,"), "{}", json);
+        assert!(json.ends_with("\"extensions\": [\n    \"GL_KHR_debug\"\n  ]\n}"), "{}", json);
+        assert_eq!(json_string("a\u{1}"), "\"a\\u0001\"");
+    }
+}
@@ -0,0 +1,123 @@
+use std::path::{Path, PathBuf};
+
+use image::RgbaImage;
+
+use crate::gpu::Framebuffer;
+
+// Reading rendered frames back from the GPU, and saving them as PNG files for reports and tests
+
+// Reads a rectangle of the framebuffer bound for reading, which is the window's back buffer
+// unless a framebuffer object is bound
+pub unsafe fn read_pixels(x: i32, y: i32, width: u32, height: u32) -> RgbaImage {
+    let mut pixels = vec![0u8; width as usize * height as usize * 4];
+    gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+    gl::ReadPixels(
+        x,
+        y,
+        width as i32,
+        height as i32,
+        gl::RGBA,
+        gl::UNSIGNED_BYTE,
+        pixels.as_mut_ptr() as *mut std::ffi::c_void,
+    );
+    image_from_gl(pixels, width, height)
+}
+
+// Reads whatever the viewport covers, which is the whole window after it has been resized
+pub unsafe fn read_viewport() -> RgbaImage {
+    let mut viewport = [0i32; 4];
+    gl::GetIntegerv(gl::VIEWPORT, viewport.as_mut_ptr());
+    read_pixels(viewport[0], viewport[1], viewport[2].max(0) as u32, viewport[3].max(0) as u32)
+}
+
+pub unsafe fn read_framebuffer(framebuffer: &Framebuffer) -> RgbaImage {
+    let mut previous = 0;
+    gl::GetIntegerv(gl::READ_FRAMEBUFFER_BINDING, &mut previous);
+    gl::BindFramebuffer(gl::READ_FRAMEBUFFER, framebuffer.id());
+    let image = read_pixels(0, 0, framebuffer.width(), framebuffer.height());
+    gl::BindFramebuffer(gl::READ_FRAMEBUFFER, previous as u32);
+    image
+}
+
+// OpenGL gives the bottom row first, while images start at the top. Alpha is made opaque, as
+// blending leaves it below 1 wherever something translucent was drawn.
+fn image_from_gl(mut pixels: Vec<u8>, width: u32, height: u32) -> RgbaImage {
+    for pixel in pixels.chunks_exact_mut(4) {
+        pixel[3] = 255;
+    }
+    let mut image = RgbaImage::from_raw(width, height, pixels).unwrap();
+    image::imageops::flip_vertical_in_place(&mut image);
+    image
+}
+
+// Saves an image as PNG, creating the folder it goes in if needed
+pub fn save_png(image: &RgbaImage, path: &Path) -> Result<(), String> {
+    if let Some(directory) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        std::fs::create_dir_all(directory)
+            .map_err(|e| format!("Failed to create {}: {}", directory.display(), e))?;
+    }
+    image.save_with_format(path, image::ImageFormat::Png)
+        .map_err(|e| format!("Failed to save {}: {}", path.display(), e))
+}
+
+// Where a screenshot taken now goes, named after the time so that none are overwritten
+pub fn screenshot_path() -> PathBuf {
+    let since_epoch = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default();
+    PathBuf::from(format!("screenshots/screenshot-{}.png", since_epoch.as_millis()))
+}
+
+// Saves every frame it is given into a folder, as frame_00000.png, frame_00001.png and so on
+pub struct Recorder {
+    directory : PathBuf,
+    frames    : u32,   // How many have been saved
+}
+
+impl Recorder {
+    pub fn new(directory: &Path) -> Result<Recorder, String> {
+        std::fs::create_dir_all(directory)
+            .map_err(|e| format!("Failed to create {}: {}", directory.display(), e))?;
+        Ok(Recorder { directory: directory.to_path_buf(), frames: 0 })
+    }
+
+    pub fn frames(&self) -> u32 {
+        self.frames
+    }
+
+    fn next_path(&self) -> PathBuf {
+        self.directory.join(format!("frame_{:05}.png", self.frames))
+    }
+
+    pub fn save(&mut self, image: &RgbaImage) -> Result<(), String> {
+        save_png(image, &self.next_path())?;
+        self.frames += 1;
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn images_are_flipped_and_made_opaque() {
+        // Two rows of one pixel, bottom row first as OpenGL gives them
+        let image = image_from_gl(vec![255, 0, 0, 10,  0, 255, 0, 128], 1, 2);
+        assert_eq!(image.get_pixel(0, 0).0, [0, 255, 0, 255]);
+        assert_eq!(image.get_pixel(0, 1).0, [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn recorded_frames_are_numbered() {
+        let directory = std::env::temp_dir().join(format!("gloom-rs-record-{}", std::process::id()));
+        let mut recorder = Recorder::new(&directory).unwrap();
+        assert!(recorder.next_path().ends_with("frame_00000.png"));
+
+        recorder.save(&RgbaImage::new(2, 2)).unwrap();
+        assert!(directory.join("frame_00000.png").exists());
+        assert!(recorder.next_path().ends_with("frame_00001.png"));
+        assert_eq!(recorder.frames(), 1);
+    }
+}
@@ -0,0 +1,140 @@
+use std::path::PathBuf;
+
+// Command line options
+
+pub const USAGE: &str = "\
+Usage: gloom-rs [options]
+
+Options:
+    --headless WxH     Render offscreen at W by H pixels instead of opening a window
+    --frames N         How many frames to render with --headless [default: 1]
+    --scene NAME       What to render with --headless: lunar, circle (assignment 1) or
+                       billboards (assignment 2) [default: lunar]
+    --screenshot FILE  Save the first frame, or the last one with --headless, as PNG
+    --record DIR       Save every frame into DIR as frame_00000.png, frame_00001.png, ...
+    --fps N            Frames per second of scene time when recording or headless [default: 60]
+    --gl-info          Print what the OpenGL driver supports as JSON, and exit
+    --help             Print this message, and exit
+
+Press F12 to save a screenshot into the screenshots folder.";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SceneName {
+    Lunar,
+    Circle,
+    Billboards,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Options {
+    pub headless   : Option<(u32, u32)>,   // The size to render at without a window
+    pub frames     : u32,
+    pub scene      : SceneName,
+    pub screenshot : Option<PathBuf>,
+    pub record     : Option<PathBuf>,   // The folder to save every frame into
+    pub fps        : u32,
+    pub gl_info    : bool,
+    pub help       : bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            headless   : None,
+            frames     : 1,
+            scene      : SceneName::Lunar,
+            screenshot : None,
+            record     : None,
+            fps        : 60,
+            gl_info    : false,
+            help       : false,
+        }
+    }
+}
+
+impl Options {
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Options, String> {
+        let mut options = Options::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+            match arg.as_str() {
+                "--headless"     => { options.headless = Some(parse_size(&value()?)?); },
+                "--frames"       => { options.frames = parse_number(&arg, &value()?)?; },
+                "--scene"        => { options.scene = parse_scene(&value()?)?; },
+                "--screenshot"   => { options.screenshot = Some(PathBuf::from(value()?)); },
+                "--record"       => { options.record = Some(PathBuf::from(value()?)); },
+                "--fps"          => { options.fps = parse_number(&arg, &value()?)?; },
+                "--gl-info"      => { options.gl_info = true; },
+                "--help" | "-h"  => { options.help = true; },
+                _ => { return Err(format!("Unknown option {}", arg)); },
+            }
+        }
+        Ok(options)
+    }
+
+    // The scene time between two frames, when it does not follow the clock
+    pub fn frame_time(&self) -> f32 {
+        1.0 / self.fps as f32
+    }
+}
+
+fn parse_number(option: &str, value: &str) -> Result<u32, String> {
+    match value.parse() {
+        Ok(number) if number > 0 => Ok(number),
+        _ => Err(format!("{} needs a positive number, not {}", option, value)),
+    }
+}
+
+fn parse_scene(value: &str) -> Result<SceneName, String> {
+    match value {
+        "lunar"      => Ok(SceneName::Lunar),
+        "circle"     => Ok(SceneName::Circle),
+        "billboards" => Ok(SceneName::Billboards),
+        _ => Err(format!("There is no scene named {}, only lunar, circle and billboards", value)),
+    }
+}
+
+// Parses a size like 800x600
+fn parse_size(value: &str) -> Result<(u32, u32), String> {
+    let mut parts = value.splitn(2, 'x');
+    let (width, height) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+    match (width.parse(), height.parse()) {
+        (Ok(width), Ok(height)) if width > 0 && height > 0 => Ok((width, height)),
+        _ => Err(format!("Expected a size like 800x600, not {}", value)),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        Options::parse(args.iter().map(|a| a.to_string()))
+    }
+
+    #[test]
+    fn options_are_parsed() {
+        assert_eq!(parse(&[]), Ok(Options::default()));
+        assert_eq!(parse(&["--gl-info"]), Ok(Options { gl_info: true, ..Options::default() }));
+        assert_eq!(parse(&["--headless", "640x480", "--frames", "30"]),
+            Ok(Options { headless: Some((640, 480)), frames: 30, ..Options::default() }));
+        assert_eq!(parse(&["--scene", "circle"]), Ok(Options { scene: SceneName::Circle, ..Options::default() }));
+        assert_eq!(parse(&["--record", "frames", "--fps", "30", "--screenshot", "shot.png"]), Ok(Options {
+            record     : Some(PathBuf::from("frames")),
+            fps        : 30,
+            screenshot : Some(PathBuf::from("shot.png")),
+            ..Options::default()
+        }));
+    }
+
+    #[test]
+    fn bad_options_are_explained() {
+        assert_eq!(parse(&["--gl-inf"]), Err("Unknown option --gl-inf".to_string()));
+        assert_eq!(parse(&["--headless"]), Err("--headless needs a value".to_string()));
+        assert_eq!(parse(&["--headless", "640x"]), Err("Expected a size like 800x600, not 640x".to_string()));
+        assert_eq!(parse(&["--scene", "teapot"]), Err("There is no scene named teapot, only lunar, circle and billboards".to_string()));
+        assert_eq!(parse(&["--frames", "0"]), Err("--frames needs a positive number, not 0".to_string()));
+    }
+}
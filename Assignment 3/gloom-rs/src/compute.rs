@@ -0,0 +1,122 @@
+use crate::shader::{Shader, ShaderBuilder, ShaderError, ShaderType};
+
+// A program made of a single compute shader, for work done on the GPU outside of drawing:
+//
+//     let particles = gpu::Buffer::storage(&initial_particles);
+//     let update = ComputeProgram::load("./shaders/particles.comp")?;
+//     particles.bind_storage(0);
+//     update.shader.set_f32("u_deltaTime", delta_time);
+//     update.dispatch_for(particle_count, 1, 1);
+//     memory_barrier(&[Barrier::VertexAttribArray]);
+//
+// Dispatches run asynchronously, so a barrier has to be placed between a dispatch writing to a
+// buffer or image and whatever reads it afterwards.
+
+pub struct ComputeProgram {
+    pub shader : Shader,   // For setting uniforms, and reloading
+}
+
+#[allow(dead_code)]
+impl ComputeProgram {
+    pub unsafe fn load(path: &str) -> Result<ComputeProgram, ShaderError> {
+        ComputeProgram::new(ShaderBuilder::new().attach_file(path)?.link()?)
+    }
+
+    // Fails unless the program consists of exactly one compute shader
+    pub fn new(shader: Shader) -> Result<ComputeProgram, ShaderError> {
+        let stages = shader.stages();
+        if stages != [ShaderType::Compute] {
+            return Err(ShaderError::WrongStages { expected: vec![ShaderType::Compute], found: stages });
+        }
+        Ok(ComputeProgram { shader })
+    }
+
+    // The `layout(local_size_x = ..., local_size_y = ..., local_size_z = ...)` of the shader
+    pub unsafe fn local_size(&self) -> [u32; 3] {
+        let mut size = [0i32; 3];
+        gl::GetProgramiv(self.shader.program.id(), gl::COMPUTE_WORK_GROUP_SIZE, size.as_mut_ptr());
+        [size[0] as u32, size[1] as u32, size[2] as u32]
+    }
+
+    // Runs x * y * z work groups
+    pub unsafe fn dispatch(&self, x: u32, y: u32, z: u32) {
+        self.shader.activate();
+        gl::DispatchCompute(x, y, z);
+    }
+
+    // Runs enough work groups to cover at least x * y * z invocations. The shader has to skip the
+    // invocations past the end itself, as the last group in each direction may stick out.
+    pub unsafe fn dispatch_for(&self, x: u32, y: u32, z: u32) {
+        let [groups_x, groups_y, groups_z] = work_groups_for([x, y, z], self.local_size());
+        self.dispatch(groups_x, groups_y, groups_z);
+    }
+}
+
+fn work_groups_for(invocations: [u32; 3], local_size: [u32; 3]) -> [u32; 3] {
+    let groups = |i: usize| invocations[i].div_ceil(local_size[i].max(1));
+    [groups(0), groups(1), groups(2)]
+}
+
+
+// Barriers
+
+// What the data written by a compute shader is about to be used as
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Barrier {
+    ShaderStorage,       // Read or written by another shader through an SSBO
+    VertexAttribArray,   // Drawn from as vertex attributes
+    ElementArray,        // Drawn from as indices
+    Command,             // Read by indirect draws and dispatches
+    Uniform,             // Read through a uniform block
+    TextureFetch,        // Sampled from as a texture
+    ShaderImageAccess,   // Read or written as an image
+    BufferUpdate,        // Read back or changed with glGetBufferSubData, glBufferSubData and the like
+    All,
+}
+
+impl From<Barrier> for gl::types::GLbitfield {
+    fn from(barrier: Barrier) -> Self {
+        match barrier {
+            Barrier::ShaderStorage      => { gl::SHADER_STORAGE_BARRIER_BIT       },
+            Barrier::VertexAttribArray  => { gl::VERTEX_ATTRIB_ARRAY_BARRIER_BIT  },
+            Barrier::ElementArray       => { gl::ELEMENT_ARRAY_BARRIER_BIT        },
+            Barrier::Command            => { gl::COMMAND_BARRIER_BIT              },
+            Barrier::Uniform            => { gl::UNIFORM_BARRIER_BIT              },
+            Barrier::TextureFetch       => { gl::TEXTURE_FETCH_BARRIER_BIT        },
+            Barrier::ShaderImageAccess  => { gl::SHADER_IMAGE_ACCESS_BARRIER_BIT  },
+            Barrier::BufferUpdate       => { gl::BUFFER_UPDATE_BARRIER_BIT        },
+            Barrier::All                => { gl::ALL_BARRIER_BITS                 },
+        }
+    }
+}
+
+fn barrier_bits(barriers: &[Barrier]) -> gl::types::GLbitfield {
+    barriers.iter().fold(0, |bits, &barrier| bits | gl::types::GLbitfield::from(barrier))
+}
+
+// Makes writes from earlier dispatches visible to the given kinds of reads after it
+#[allow(dead_code)]
+pub unsafe fn memory_barrier(barriers: &[Barrier]) {
+    gl::MemoryBarrier(barrier_bits(barriers));
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn partial_work_groups_are_rounded_up() {
+        assert_eq!(work_groups_for([1000, 1, 1], [64, 1, 1]), [16, 1, 1]);
+        assert_eq!(work_groups_for([512, 512, 1], [16, 16, 1]), [32, 32, 1]);
+        assert_eq!(work_groups_for([0, 1, 1], [64, 1, 1]), [0, 1, 1]);
+    }
+
+    #[test]
+    fn barriers_are_combined() {
+        assert_eq!(barrier_bits(&[]), 0);
+        assert_eq!(barrier_bits(&[Barrier::ShaderStorage, Barrier::VertexAttribArray]),
+            gl::SHADER_STORAGE_BARRIER_BIT | gl::VERTEX_ATTRIB_ARRAY_BARRIER_BIT);
+    }
+}
@@ -0,0 +1,258 @@
+use std::{
+    backtrace::Backtrace,
+    collections::HashMap,
+    ffi::CStr,
+    fmt,
+    sync::Mutex,
+};
+
+// Messages from the driver's debug output (KHR_debug), such as errors, misuse of the API and
+// performance hints. Each severity can be ignored, printed, or treated as fatal:
+//
+//     gl_debug::install(DebugOutput::new().policy(DebugSeverity::Medium, Policy::Panic));
+//
+// and the render loop calls gl_debug::check() once per frame, which panics if a fatal message
+// arrived. The callback cannot panic itself, since unwinding through the driver aborts the
+// process, but with GL_DEBUG_OUTPUT_SYNCHRONOUS it runs inside the offending GL call, so a
+// backtrace taken there shows where that call was made.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DebugSource {
+    Api,
+    WindowSystem,
+    ShaderCompiler,
+    ThirdParty,
+    Application,
+    Other,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DebugType {
+    Error,
+    DeprecatedBehavior,
+    UndefinedBehavior,
+    Portability,
+    Performance,
+    Marker,
+    PushGroup,
+    PopGroup,
+    Other,
+}
+
+// Ordered from least to most severe
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum DebugSeverity {
+    Notification,
+    Low,
+    Medium,
+    High,
+}
+
+impl DebugSource {
+    fn from_gl(source: gl::types::GLenum) -> DebugSource {
+        match source {
+            gl::DEBUG_SOURCE_API             => DebugSource::Api,
+            gl::DEBUG_SOURCE_WINDOW_SYSTEM   => DebugSource::WindowSystem,
+            gl::DEBUG_SOURCE_SHADER_COMPILER => DebugSource::ShaderCompiler,
+            gl::DEBUG_SOURCE_THIRD_PARTY     => DebugSource::ThirdParty,
+            gl::DEBUG_SOURCE_APPLICATION     => DebugSource::Application,
+            _                                => DebugSource::Other,
+        }
+    }
+}
+
+impl DebugType {
+    fn from_gl(message_type: gl::types::GLenum) -> DebugType {
+        match message_type {
+            gl::DEBUG_TYPE_ERROR               => DebugType::Error,
+            gl::DEBUG_TYPE_DEPRECATED_BEHAVIOR => DebugType::DeprecatedBehavior,
+            gl::DEBUG_TYPE_UNDEFINED_BEHAVIOR  => DebugType::UndefinedBehavior,
+            gl::DEBUG_TYPE_PORTABILITY         => DebugType::Portability,
+            gl::DEBUG_TYPE_PERFORMANCE         => DebugType::Performance,
+            gl::DEBUG_TYPE_MARKER              => DebugType::Marker,
+            gl::DEBUG_TYPE_PUSH_GROUP          => DebugType::PushGroup,
+            gl::DEBUG_TYPE_POP_GROUP           => DebugType::PopGroup,
+            _                                  => DebugType::Other,
+        }
+    }
+}
+
+impl DebugSeverity {
+    fn from_gl(severity: gl::types::GLenum) -> DebugSeverity {
+        match severity {
+            gl::DEBUG_SEVERITY_HIGH   => DebugSeverity::High,
+            gl::DEBUG_SEVERITY_MEDIUM => DebugSeverity::Medium,
+            gl::DEBUG_SEVERITY_LOW    => DebugSeverity::Low,
+            _                         => DebugSeverity::Notification,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DebugMessage {
+    pub source       : DebugSource,
+    pub message_type : DebugType,
+    pub severity     : DebugSeverity,
+    pub id           : u32,
+    pub text         : String,
+}
+
+impl fmt::Display for DebugMessage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "GL {:?} {:?} from {:?} ({}): {}",
+            self.severity, self.message_type, self.source, self.id, self.text.trim_end())
+    }
+}
+
+// What is done with messages of a given severity
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Policy {
+    Ignore,
+    Log,
+    Panic,
+}
+
+pub struct DebugOutput {
+    policies   : [Policy; 4],                                   // Indexed by DebugSeverity
+    backtraces : bool,                                          // Whether to show where the GL call was made
+    seen       : HashMap<(DebugSource, DebugType, u32), usize>, // How often each message has arrived
+    fatal      : Option<String>,                                // Waiting to be raised by check()
+}
+
+impl DebugOutput {
+    // Notifications are ignored, errors of high severity are fatal, everything else is printed
+    pub fn new() -> DebugOutput {
+        DebugOutput {
+            policies   : [Policy::Ignore, Policy::Log, Policy::Log, Policy::Panic],
+            backtraces : false,
+            seen       : HashMap::new(),
+            fatal      : None,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn policy(mut self, severity: DebugSeverity, policy: Policy) -> DebugOutput {
+        self.policies[severity as usize] = policy;
+        self
+    }
+
+    pub fn backtraces(mut self, backtraces: bool) -> DebugOutput {
+        self.backtraces = backtraces;
+        self
+    }
+
+    // Returns what to print for the message, if anything. Drivers tend to repeat the same
+    // message every frame, so only the first of each ID is printed.
+    fn receive(&mut self, message: &DebugMessage) -> Option<String> {
+        let policy = self.policies[message.severity as usize];
+        if policy == Policy::Ignore {
+            return None;
+        }
+        let count = self.seen.entry((message.source, message.message_type, message.id)).or_insert(0);
+        *count += 1;
+        if *count > 1 && policy == Policy::Log {
+            return None;
+        }
+
+        let mut report = message.to_string();
+        if self.backtraces {
+            report += &format!("\n{}", Backtrace::force_capture());
+        }
+        if policy == Policy::Panic {
+            self.fatal.get_or_insert(report);
+            return None;
+        }
+        Some(report)
+    }
+
+    // How many times messages already printed have come again since
+    #[allow(dead_code)]
+    pub fn repeats(&self) -> usize {
+        self.seen.values().map(|count| count - 1).sum()
+    }
+}
+
+static OUTPUT: Mutex<Option<DebugOutput>> = Mutex::new(None);
+
+// Enables debug output on the current context and sends it to `output`
+pub unsafe fn install(output: DebugOutput) {
+    *OUTPUT.lock().unwrap() = Some(output);
+    gl::Enable(gl::DEBUG_OUTPUT);
+    gl::Enable(gl::DEBUG_OUTPUT_SYNCHRONOUS);
+    gl::DebugMessageCallback(Some(debug_callback), std::ptr::null());
+}
+
+// Panics with the first fatal message received since the last call
+pub fn check() {
+    let fatal = OUTPUT.lock().unwrap().as_mut().and_then(|output| output.fatal.take());
+    if let Some(report) = fatal {
+        panic!("{}", report);
+    }
+}
+
+extern "system" fn debug_callback(
+    source: u32, message_type: u32, id: u32,
+    severity: u32, length: i32,
+    text: *const gl::types::GLchar, _data: *mut std::ffi::c_void
+) {
+    // The driver owns the text, and it is only valid until the callback returns
+    let text = unsafe {
+        if length >= 0 {
+            String::from_utf8_lossy(std::slice::from_raw_parts(text as *const u8, length as usize)).to_string()
+        } else {
+            CStr::from_ptr(text).to_string_lossy().to_string()
+        }
+    };
+    let message = DebugMessage {
+        source       : DebugSource::from_gl(source),
+        message_type : DebugType::from_gl(message_type),
+        severity     : DebugSeverity::from_gl(severity),
+        id,
+        text,
+    };
+    // A poisoned lock means the render thread is already going down
+    if let Ok(mut output) = OUTPUT.lock() {
+        if let Some(report) = output.as_mut().and_then(|output| output.receive(&message)) {
+            println!("{}", report);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(severity: DebugSeverity, id: u32) -> DebugMessage {
+        DebugMessage {
+            source       : DebugSource::Api,
+            message_type : DebugType::Error,
+            severity,
+            id,
+            text         : "GL_INVALID_OPERATION in glDrawElements\n".to_string(),
+        }
+    }
+
+    #[test]
+    fn repeated_messages_are_printed_once() {
+        let mut output = DebugOutput::new();
+        assert_eq!(output.receive(&message(DebugSeverity::Medium, 7)).unwrap(),
+            "GL Medium Error from Api (7): GL_INVALID_OPERATION in glDrawElements");
+        assert_eq!(output.receive(&message(DebugSeverity::Medium, 7)), None);
+        assert!(output.receive(&message(DebugSeverity::Medium, 8)).is_some());
+        assert_eq!(output.repeats(), 1);
+    }
+
+    #[test]
+    fn policies_follow_the_severity() {
+        let mut output = DebugOutput::new().policy(DebugSeverity::Low, Policy::Ignore);
+        assert_eq!(output.receive(&message(DebugSeverity::Notification, 1)), None);
+        assert_eq!(output.receive(&message(DebugSeverity::Low, 2)), None);
+        assert_eq!(output.seen.len(), 0);
+
+        // Fatal messages are kept for check(), and the first one wins
+        assert_eq!(output.receive(&message(DebugSeverity::High, 3)), None);
+        assert_eq!(output.receive(&message(DebugSeverity::High, 4)), None);
+        assert!(output.fatal.unwrap().starts_with("GL High Error from Api (3)"));
+    }
+}
@@ -0,0 +1,406 @@
+use std::fmt;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// Owners of OpenGL objects, which give their names back when dropped.
+//
+// An OpenGL object can only be deleted on the thread its context is current on, while the Rust
+// values owning them may be dropped anywhere. Dropping one therefore only queues its name, and the
+// render thread deletes whatever is queued by calling delete_pending() once per frame. Every
+// object is also counted, so leaks show up in live_counts().
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResourceKind {
+    VertexArray,
+    Buffer,
+    Program,
+    Framebuffer,
+    Renderbuffer,
+    Texture,
+}
+
+static PENDING: Mutex<Vec<(ResourceKind, u32)>> = Mutex::new(Vec::new());
+static LIVE: [AtomicUsize; 6] = [
+    AtomicUsize::new(0), AtomicUsize::new(0), AtomicUsize::new(0), AtomicUsize::new(0), AtomicUsize::new(0),
+    AtomicUsize::new(0),
+];
+
+fn register(kind: ResourceKind) {
+    LIVE[kind as usize].fetch_add(1, Ordering::Relaxed);
+}
+
+fn release(kind: ResourceKind, id: u32) {
+    LIVE[kind as usize].fetch_sub(1, Ordering::Relaxed);
+    PENDING.lock().unwrap().push((kind, id));
+}
+
+// How many objects of each kind are owned right now, and how many are waiting to be deleted
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LiveCounts {
+    pub vertex_arrays : usize,
+    pub buffers       : usize,
+    pub programs      : usize,
+    pub framebuffers  : usize,
+    pub renderbuffers : usize,
+    pub textures      : usize,
+    pub pending       : usize,
+}
+
+impl fmt::Display for LiveCounts {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} vertex arrays, {} buffers, {} programs, {} framebuffers, {} renderbuffers, {} textures ({} waiting to be deleted)",
+            self.vertex_arrays, self.buffers, self.programs, self.framebuffers, self.renderbuffers, self.textures, self.pending)
+    }
+}
+
+pub fn live_counts() -> LiveCounts {
+    LiveCounts {
+        vertex_arrays : LIVE[ResourceKind::VertexArray as usize].load(Ordering::Relaxed),
+        buffers       : LIVE[ResourceKind::Buffer as usize].load(Ordering::Relaxed),
+        programs      : LIVE[ResourceKind::Program as usize].load(Ordering::Relaxed),
+        framebuffers  : LIVE[ResourceKind::Framebuffer as usize].load(Ordering::Relaxed),
+        renderbuffers : LIVE[ResourceKind::Renderbuffer as usize].load(Ordering::Relaxed),
+        textures      : LIVE[ResourceKind::Texture as usize].load(Ordering::Relaxed),
+        pending       : PENDING.lock().unwrap().len(),
+    }
+}
+
+// Deletes every object dropped since the last call, and returns how many there were. Must be
+// called on the render thread.
+pub unsafe fn delete_pending() -> usize {
+    let pending: Vec<(ResourceKind, u32)> = std::mem::take(&mut *PENDING.lock().unwrap());
+    for &(kind, id) in &pending {
+        match kind {
+            ResourceKind::VertexArray  => gl::DeleteVertexArrays(1, &id),
+            ResourceKind::Buffer       => gl::DeleteBuffers(1, &id),
+            ResourceKind::Program      => gl::DeleteProgram(id),
+            ResourceKind::Framebuffer  => gl::DeleteFramebuffers(1, &id),
+            ResourceKind::Renderbuffer => gl::DeleteRenderbuffers(1, &id),
+            ResourceKind::Texture      => gl::DeleteTextures(1, &id),
+        }
+    }
+    pending.len()
+}
+
+
+// Vertex array
+
+// A VAO, along with the buffers it reads from, which live exactly as long as it does
+pub struct VertexArray {
+    id      : u32,
+    buffers : Vec<Buffer>,
+}
+
+impl VertexArray {
+    pub unsafe fn new() -> VertexArray {
+        let mut id: u32 = 0;
+        gl::GenVertexArrays(1, &mut id);
+        VertexArray::from_raw(id)
+    }
+
+    // Takes ownership of a VAO created elsewhere
+    pub unsafe fn from_raw(id: u32) -> VertexArray {
+        register(ResourceKind::VertexArray);
+        VertexArray { id, buffers: vec![] }
+    }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    pub unsafe fn bind(&self) {
+        gl::BindVertexArray(self.id);
+    }
+
+    // Keeps a buffer alive for as long as this VAO
+    pub fn attach(&mut self, buffer: Buffer) {
+        self.buffers.push(buffer);
+    }
+}
+
+impl Drop for VertexArray {
+    fn drop(&mut self) {
+        release(ResourceKind::VertexArray, self.id);
+    }
+}
+
+
+// Buffer
+
+pub struct Buffer {
+    id : u32,
+}
+
+impl Buffer {
+    pub unsafe fn new() -> Buffer {
+        let mut id: u32 = 0;
+        gl::GenBuffers(1, &mut id);
+        Buffer::from_raw(id)
+    }
+
+    // Takes ownership of a buffer created elsewhere
+    pub unsafe fn from_raw(id: u32) -> Buffer {
+        register(ResourceKind::Buffer);
+        Buffer { id }
+    }
+
+    // Creates a buffer, binds it to `target` and fills it with `data`
+    pub unsafe fn with_data<T>(target: gl::types::GLenum, data: &[T]) -> Buffer {
+        Buffer::with_usage(target, data, gl::STATIC_DRAW)
+    }
+
+    unsafe fn with_usage<T>(target: gl::types::GLenum, data: &[T], usage: gl::types::GLenum) -> Buffer {
+        let buffer = Buffer::new();
+        gl::BindBuffer(target, buffer.id);
+        gl::BufferData(
+            target,
+            std::mem::size_of_val(data) as isize,
+            if data.is_empty() { std::ptr::null() } else { data.as_ptr() as *const std::ffi::c_void },
+            usage,
+        );
+        buffer
+    }
+
+    // Shader storage buffers, written by compute shaders and read back by other shaders. The
+    // layout of `T` has to match the std430 layout of the block in the shader, where a vec3 takes
+    // up as much room as a vec4.
+
+    // Creates a buffer for the GPU to both write and read
+    #[allow(dead_code)]
+    pub unsafe fn storage<T>(data: &[T]) -> Buffer {
+        Buffer::with_usage(gl::SHADER_STORAGE_BUFFER, data, gl::DYNAMIC_COPY)
+    }
+
+    // Binds the whole buffer to the SSBO binding point `binding`
+    #[allow(dead_code)]
+    pub unsafe fn bind_storage(&self, binding: u32) {
+        gl::BindBufferBase(gl::SHADER_STORAGE_BUFFER, binding, self.id);
+    }
+
+    // Binds `size` bytes starting at `offset`, which has to be a multiple of
+    // GL_SHADER_STORAGE_BUFFER_OFFSET_ALIGNMENT
+    #[allow(dead_code)]
+    pub unsafe fn bind_storage_range(&self, binding: u32, offset: usize, size: usize) {
+        gl::BindBufferRange(gl::SHADER_STORAGE_BUFFER, binding, self.id, offset as isize, size as isize);
+    }
+
+    // Copies the start of the buffer back into `data`. Writes made by shaders are only seen after
+    // a memory barrier for BufferUpdate.
+    #[allow(dead_code)]
+    pub unsafe fn read_storage<T: Copy>(&self, data: &mut [T]) {
+        gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, self.id);
+        gl::GetBufferSubData(
+            gl::SHADER_STORAGE_BUFFER,
+            0,
+            std::mem::size_of_val(data) as isize,
+            data.as_mut_ptr() as *mut std::ffi::c_void,
+        );
+    }
+
+    #[allow(dead_code)]
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+}
+
+impl Drop for Buffer {
+    fn drop(&mut self) {
+        release(ResourceKind::Buffer, self.id);
+    }
+}
+
+
+// Program
+
+pub struct Program {
+    id : u32,
+}
+
+impl Program {
+    pub unsafe fn new() -> Program {
+        Program::from_raw(gl::CreateProgram())
+    }
+
+    // Takes ownership of a program created elsewhere
+    pub unsafe fn from_raw(id: u32) -> Program {
+        register(ResourceKind::Program);
+        Program { id }
+    }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+}
+
+impl Drop for Program {
+    fn drop(&mut self) {
+        release(ResourceKind::Program, self.id);
+    }
+}
+
+
+// Framebuffer
+
+// An FBO to render into instead of the window, along with the renderbuffers it draws to
+pub struct Framebuffer {
+    id          : u32,
+    width       : u32,
+    height      : u32,
+    attachments : Vec<Renderbuffer>,
+}
+
+impl Framebuffer {
+    // Creates a framebuffer with an RGBA8 color buffer and a 24 bit depth buffer
+    pub unsafe fn new(width: u32, height: u32) -> Result<Framebuffer, String> {
+        let mut id: u32 = 0;
+        gl::GenFramebuffers(1, &mut id);
+        let mut framebuffer = Framebuffer::from_raw(id, width, height);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, id);
+
+        let color = Renderbuffer::new(gl::RGBA8, width, height);
+        gl::FramebufferRenderbuffer(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::RENDERBUFFER, color.id);
+        framebuffer.attachments.push(color);
+        let depth = Renderbuffer::new(gl::DEPTH24_STENCIL8, width, height);
+        gl::FramebufferRenderbuffer(gl::FRAMEBUFFER, gl::DEPTH_STENCIL_ATTACHMENT, gl::RENDERBUFFER, depth.id);
+        framebuffer.attachments.push(depth);
+
+        let status = gl::CheckFramebufferStatus(gl::FRAMEBUFFER);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        if status != gl::FRAMEBUFFER_COMPLETE {
+            return Err(format!("The {}x{} framebuffer is incomplete (status {:#x})", width, height, status));
+        }
+        Ok(framebuffer)
+    }
+
+    // Takes ownership of a framebuffer created elsewhere
+    pub unsafe fn from_raw(id: u32, width: u32, height: u32) -> Framebuffer {
+        register(ResourceKind::Framebuffer);
+        Framebuffer { id, width, height, attachments: vec![] }
+    }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    // Draws into this framebuffer from now on, with the viewport covering all of it
+    pub unsafe fn bind(&self) {
+        gl::BindFramebuffer(gl::FRAMEBUFFER, self.id);
+        gl::Viewport(0, 0, self.width as i32, self.height as i32);
+    }
+}
+
+impl Drop for Framebuffer {
+    fn drop(&mut self) {
+        release(ResourceKind::Framebuffer, self.id);
+    }
+}
+
+pub struct Renderbuffer {
+    id : u32,
+}
+
+impl Renderbuffer {
+    pub unsafe fn new(internal_format: gl::types::GLenum, width: u32, height: u32) -> Renderbuffer {
+        let mut id: u32 = 0;
+        gl::GenRenderbuffers(1, &mut id);
+        gl::BindRenderbuffer(gl::RENDERBUFFER, id);
+        gl::RenderbufferStorage(gl::RENDERBUFFER, internal_format, width as i32, height as i32);
+        gl::BindRenderbuffer(gl::RENDERBUFFER, 0);
+        Renderbuffer::from_raw(id)
+    }
+
+    // Takes ownership of a renderbuffer created elsewhere
+    pub unsafe fn from_raw(id: u32) -> Renderbuffer {
+        register(ResourceKind::Renderbuffer);
+        Renderbuffer { id }
+    }
+}
+
+impl Drop for Renderbuffer {
+    fn drop(&mut self) {
+        release(ResourceKind::Renderbuffer, self.id);
+    }
+}
+
+
+// Texture
+
+pub struct Texture {
+    id : u32,
+}
+
+impl Texture {
+    pub unsafe fn new() -> Texture {
+        let mut id: u32 = 0;
+        gl::GenTextures(1, &mut id);
+        Texture::from_raw(id)
+    }
+
+    // Takes ownership of a texture created elsewhere
+    pub unsafe fn from_raw(id: u32) -> Texture {
+        register(ResourceKind::Texture);
+        Texture { id }
+    }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+}
+
+impl Drop for Texture {
+    fn drop(&mut self) {
+        release(ResourceKind::Texture, self.id);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Only this test creates programs, so their count is not disturbed by tests running alongside
+    #[test]
+    fn dropped_objects_are_queued_and_uncounted() {
+        let before = live_counts().programs;
+        let program = unsafe { Program::from_raw(4242) };
+        assert_eq!(live_counts().programs, before + 1);
+
+        drop(program);
+        assert_eq!(live_counts().programs, before);
+        assert!(PENDING.lock().unwrap().contains(&(ResourceKind::Program, 4242)));
+    }
+
+    #[test]
+    fn renderbuffers_go_with_their_framebuffer() {
+        let mut framebuffer = unsafe { Framebuffer::from_raw(9003, 64, 32) };
+        framebuffer.attachments.push(unsafe { Renderbuffer::from_raw(9004) });
+        drop(framebuffer);
+        let pending = PENDING.lock().unwrap();
+        assert!(pending.contains(&(ResourceKind::Framebuffer, 9003)));
+        assert!(pending.contains(&(ResourceKind::Renderbuffer, 9004)));
+    }
+
+    #[test]
+    fn dropped_textures_are_queued() {
+        drop(unsafe { Texture::from_raw(9005) });
+        assert!(PENDING.lock().unwrap().contains(&(ResourceKind::Texture, 9005)));
+    }
+
+    #[test]
+    fn buffers_go_with_their_vertex_array() {
+        let mut vao = unsafe { VertexArray::from_raw(9001) };
+        vao.attach(unsafe { Buffer::from_raw(9002) });
+        drop(vao);
+        let pending = PENDING.lock().unwrap();
+        assert!(pending.contains(&(ResourceKind::VertexArray, 9001)));
+        assert!(pending.contains(&(ResourceKind::Buffer, 9002)));
+    }
+}
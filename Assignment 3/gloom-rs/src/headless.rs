@@ -0,0 +1,226 @@
+// An OpenGL context without a window, for rendering on machines with no display, like a CI box.
+// glutin can only create those through an event loop, which needs a display itself, so this talks
+// to EGL directly. Mesa's surfaceless platform works without any display or GPU at all, using the
+// llvmpipe software renderer, and other drivers get the default display with a tiny pbuffer.
+//
+// Nothing is drawn to the context itself, so rendering has to go to a gpu::Framebuffer.
+
+#[cfg(unix)]
+pub use self::egl::HeadlessContext;
+
+#[cfg(unix)]
+mod egl {
+    use std::{
+        ffi::{CStr, CString},
+        os::raw::{c_char, c_void},
+        ptr,
+    };
+
+    type EGLDisplay = *mut c_void;
+    type EGLConfig  = *mut c_void;
+    type EGLContext = *mut c_void;
+    type EGLSurface = *mut c_void;
+    type EGLint     = i32;
+    type EGLenum    = u32;
+    type EGLBoolean = u32;
+
+    const EGL_NONE                            : EGLint  = 0x3038;
+    const EGL_EXTENSIONS                      : EGLint  = 0x3055;
+    const EGL_SURFACE_TYPE                    : EGLint  = 0x3033;
+    const EGL_PBUFFER_BIT                     : EGLint  = 0x0001;
+    const EGL_RENDERABLE_TYPE                 : EGLint  = 0x3040;
+    const EGL_OPENGL_BIT                      : EGLint  = 0x0008;
+    const EGL_WIDTH                           : EGLint  = 0x3057;
+    const EGL_HEIGHT                          : EGLint  = 0x3056;
+    const EGL_OPENGL_API                      : EGLenum = 0x30A2;
+    const EGL_CONTEXT_MAJOR_VERSION           : EGLint  = 0x3098;
+    const EGL_CONTEXT_MINOR_VERSION           : EGLint  = 0x30FB;
+    const EGL_CONTEXT_OPENGL_PROFILE_MASK     : EGLint  = 0x30FD;
+    const EGL_CONTEXT_OPENGL_CORE_PROFILE_BIT : EGLint  = 0x0001;
+    const EGL_PLATFORM_SURFACELESS_MESA       : EGLenum = 0x31DD;
+
+    // The few EGL functions needed, looked up in libEGL at runtime so that building does not need it
+    struct Egl {
+        get_proc_address       : unsafe extern "system" fn(*const c_char) -> *const c_void,
+        get_display            : unsafe extern "system" fn(*mut c_void) -> EGLDisplay,
+        initialize             : unsafe extern "system" fn(EGLDisplay, *mut EGLint, *mut EGLint) -> EGLBoolean,
+        terminate              : unsafe extern "system" fn(EGLDisplay) -> EGLBoolean,
+        query_string           : unsafe extern "system" fn(EGLDisplay, EGLint) -> *const c_char,
+        bind_api               : unsafe extern "system" fn(EGLenum) -> EGLBoolean,
+        choose_config          : unsafe extern "system" fn(EGLDisplay, *const EGLint, *mut EGLConfig, EGLint, *mut EGLint) -> EGLBoolean,
+        create_context         : unsafe extern "system" fn(EGLDisplay, EGLConfig, EGLContext, *const EGLint) -> EGLContext,
+        destroy_context        : unsafe extern "system" fn(EGLDisplay, EGLContext) -> EGLBoolean,
+        create_pbuffer_surface : unsafe extern "system" fn(EGLDisplay, EGLConfig, *const EGLint) -> EGLSurface,
+        destroy_surface        : unsafe extern "system" fn(EGLDisplay, EGLSurface) -> EGLBoolean,
+        make_current           : unsafe extern "system" fn(EGLDisplay, EGLSurface, EGLSurface, EGLContext) -> EGLBoolean,
+        get_error              : unsafe extern "system" fn() -> EGLint,
+    }
+
+    type GetPlatformDisplay = unsafe extern "system" fn(EGLenum, *mut c_void, *const EGLint) -> EGLDisplay;
+
+    // Looks up a function in a library opened with dlopen, as the function pointer type `F`
+    unsafe fn symbol<F>(library: *mut c_void, name: &str) -> Result<F, String> {
+        assert_eq!(std::mem::size_of::<F>(), std::mem::size_of::<*mut c_void>());
+        let symbol = libc::dlsym(library, CString::new(name).unwrap().as_ptr());
+        if symbol.is_null() {
+            return Err(format!("libEGL has no {}", name));
+        }
+        Ok(std::mem::transmute_copy(&symbol))
+    }
+
+    impl Egl {
+        unsafe fn load() -> Result<Egl, String> {
+            let library = ["libEGL.so.1", "libEGL.so"].iter()
+                .map(|name| libc::dlopen(CString::new(*name).unwrap().as_ptr(), libc::RTLD_NOW | libc::RTLD_GLOBAL))
+                .find(|handle| !handle.is_null())
+                .ok_or("Failed to load libEGL, which headless rendering needs")?;
+
+            Ok(Egl {
+                get_proc_address       : symbol(library, "eglGetProcAddress")?,
+                get_display            : symbol(library, "eglGetDisplay")?,
+                initialize             : symbol(library, "eglInitialize")?,
+                terminate              : symbol(library, "eglTerminate")?,
+                query_string           : symbol(library, "eglQueryString")?,
+                bind_api               : symbol(library, "eglBindAPI")?,
+                choose_config          : symbol(library, "eglChooseConfig")?,
+                create_context         : symbol(library, "eglCreateContext")?,
+                destroy_context        : symbol(library, "eglDestroyContext")?,
+                create_pbuffer_surface : symbol(library, "eglCreatePbufferSurface")?,
+                destroy_surface        : symbol(library, "eglDestroySurface")?,
+                make_current           : symbol(library, "eglMakeCurrent")?,
+                get_error              : symbol(library, "eglGetError")?,
+            })
+        }
+
+        // The space separated extensions of a display, or the client extensions for a null display
+        unsafe fn extensions(&self, display: EGLDisplay) -> Vec<String> {
+            let extensions = (self.query_string)(display, EGL_EXTENSIONS);
+            if extensions.is_null() {
+                return vec![];
+            }
+            CStr::from_ptr(extensions).to_string_lossy().split_whitespace().map(|e| e.to_string()).collect()
+        }
+
+        unsafe fn error(&self, what: &str) -> String {
+            format!("Failed to {} (EGL error {:#x})", what, (self.get_error)())
+        }
+    }
+
+    pub struct HeadlessContext {
+        egl      : Egl,
+        display  : EGLDisplay,
+        context  : EGLContext,
+        surface  : EGLSurface,   // Null when the context is surfaceless
+        platform : &'static str,
+    }
+
+    impl HeadlessContext {
+        // Creates a core profile context of at least the given version, and makes it current
+        pub unsafe fn new(major: i32, minor: i32) -> Result<HeadlessContext, String> {
+            let egl = Egl::load()?;
+
+            let client_extensions = egl.extensions(ptr::null_mut());
+            let get_platform_display = (egl.get_proc_address)(b"eglGetPlatformDisplayEXT\0".as_ptr() as *const c_char);
+            let (display, platform) = if client_extensions.iter().any(|e| e == "EGL_MESA_platform_surfaceless") && !get_platform_display.is_null() {
+                let get_platform_display: GetPlatformDisplay = std::mem::transmute(get_platform_display);
+                (get_platform_display(EGL_PLATFORM_SURFACELESS_MESA, ptr::null_mut(), ptr::null()), "surfaceless")
+            } else {
+                ((egl.get_display)(ptr::null_mut()), "pbuffer")
+            };
+            if display.is_null() {
+                return Err(egl.error("get an EGL display"));
+            }
+            let (mut egl_major, mut egl_minor) = (0, 0);
+            if (egl.initialize)(display, &mut egl_major, &mut egl_minor) == 0 {
+                return Err(egl.error("initialize EGL"));
+            }
+
+            // Drivers without surfaceless contexts need a surface to make the context current with
+            let needs_surface = !egl.extensions(display).iter().any(|e| e == "EGL_KHR_surfaceless_context");
+            let surface_type = if needs_surface { EGL_PBUFFER_BIT } else { 0 };
+            let config_attributes = [
+                EGL_RENDERABLE_TYPE, EGL_OPENGL_BIT,
+                EGL_SURFACE_TYPE, surface_type,
+                EGL_NONE,
+            ];
+            let mut config: EGLConfig = ptr::null_mut();
+            let mut config_count = 0;
+            if (egl.choose_config)(display, config_attributes.as_ptr(), &mut config, 1, &mut config_count) == 0 || config_count == 0 {
+                (egl.terminate)(display);
+                return Err(egl.error("find an EGL config for desktop OpenGL"));
+            }
+
+            (egl.bind_api)(EGL_OPENGL_API);
+            let context_attributes = [
+                EGL_CONTEXT_MAJOR_VERSION, major,
+                EGL_CONTEXT_MINOR_VERSION, minor,
+                EGL_CONTEXT_OPENGL_PROFILE_MASK, EGL_CONTEXT_OPENGL_CORE_PROFILE_BIT,
+                EGL_NONE,
+            ];
+            let context = (egl.create_context)(display, config, ptr::null_mut(), context_attributes.as_ptr());
+            if context.is_null() {
+                let error = egl.error(&format!("create an OpenGL {}.{} context", major, minor));
+                (egl.terminate)(display);
+                return Err(error);
+            }
+
+            let surface = if needs_surface {
+                let surface_attributes = [EGL_WIDTH, 1, EGL_HEIGHT, 1, EGL_NONE];
+                (egl.create_pbuffer_surface)(display, config, surface_attributes.as_ptr())
+            } else {
+                ptr::null_mut()
+            };
+            let headless = HeadlessContext { egl, display, context, surface, platform };
+            if needs_surface && surface.is_null() {
+                return Err(headless.egl.error("create a pbuffer surface"));
+            }
+            if (headless.egl.make_current)(display, surface, surface, context) == 0 {
+                return Err(headless.egl.error("make the headless context current"));
+            }
+            Ok(headless)
+        }
+
+        // For gl::load_with
+        pub fn get_proc_address(&self, symbol: &str) -> *const c_void {
+            let symbol = CString::new(symbol).unwrap();
+            unsafe { (self.egl.get_proc_address)(symbol.as_ptr()) }
+        }
+
+        // How the context was created, either "surfaceless" or "pbuffer"
+        pub fn platform(&self) -> &'static str {
+            self.platform
+        }
+    }
+
+    impl Drop for HeadlessContext {
+        fn drop(&mut self) {
+            unsafe {
+                (self.egl.make_current)(self.display, ptr::null_mut(), ptr::null_mut(), ptr::null_mut());
+                (self.egl.destroy_context)(self.display, self.context);
+                if !self.surface.is_null() {
+                    (self.egl.destroy_surface)(self.display, self.surface);
+                }
+                (self.egl.terminate)(self.display);
+            }
+        }
+    }
+}
+
+// EGL is looked up with dlopen, so elsewhere creating the context just fails
+#[cfg(not(unix))]
+pub struct HeadlessContext;
+
+#[cfg(not(unix))]
+impl HeadlessContext {
+    pub unsafe fn new(_major: i32, _minor: i32) -> Result<HeadlessContext, String> {
+        Err("Headless rendering needs EGL, which is only looked for on Linux and other Unix systems".to_string())
+    }
+
+    pub fn get_proc_address(&self, _symbol: &str) -> *const std::os::raw::c_void {
+        std::ptr::null()
+    }
+
+    pub fn platform(&self) -> &'static str {
+        "unsupported"
+    }
+}
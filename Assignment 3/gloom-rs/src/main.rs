@@ -16,12 +16,25 @@ mod shader;
 mod util;
 mod mesh;
 mod scene_graph;
+mod scene_file;
+mod articulated_model;
 mod toolbox;
+mod texture;
+mod vertex;
+mod gpu;
+mod reflection;
+mod preprocessor;
+mod compute;
+mod gl_debug;
+mod capabilities;
+mod cli;
+mod headless;
+mod capture;
+mod assignment_scenes;
 
 use glutin::event::{Event, WindowEvent, DeviceEvent, KeyboardInput, ElementState::{Pressed, Released}, VirtualKeyCode::{self, *}};
 use glutin::event_loop::ControlFlow;
-use scene_graph::SceneNode;
-use toolbox::simple_heading_animation;
+use scene_graph::{SceneGraph, NodeId};
 
 // initial window size
 const INITIAL_SCREEN_W: u32 = 800;
@@ -31,24 +44,28 @@ const INITIAL_SCREEN_H: u32 = 600;
 
 // Get the size of an arbitrary array of numbers measured in bytes
 // Example usage:  byte_size_of_array(my_array)
+#[allow(dead_code)]
 fn byte_size_of_array<T>(val: &[T]) -> isize {
     std::mem::size_of_val(&val[..]) as isize
 }
 
 // Get the OpenGL-compatible pointer to an arbitrary array of numbers
 // Example usage:  pointer_to_array(my_array)
+#[allow(dead_code)]
 fn pointer_to_array<T>(val: &[T]) -> *const c_void {
     &val[0] as *const T as *const c_void
 }
 
 // Get the size of the given type in bytes
 // Example usage:  size_of::<u64>()
+#[allow(dead_code)]
 fn size_of<T>() -> i32 {
     mem::size_of::<T>() as i32
 }
 
 // Get an offset in bytes for n units of type T, represented as a relative pointer
 // Example usage:  offset::<u64>(4)
+#[allow(dead_code)]
 fn offset<T>(n: u32) -> *const c_void {
     (n * mem::size_of::<T>() as u32) as *const T as *const c_void
 }
@@ -57,116 +74,83 @@ fn offset<T>(n: u32) -> *const c_void {
 // ptr::null()
 
 
-// Creates a VAO and returns its id
-unsafe fn create_vao(vertices: &Vec<f32>, indices: &Vec<u32>, colors: &Vec<f32>, normals: &Vec<f32>) -> u32 {
+// Creates a VAO, which owns the buffers it reads from
+// Makes sure a VAO feeds the vertex shader what it expects, since a mismatch otherwise only shows
+// as a black or garbled mesh. Texture coordinates and tangents are only read for meshes with
+// textures and normal maps.
+fn check_vertex_layout(shader: &shader::Shader, name: &str, material: &mesh::Material, layout: &vertex::VertexLayout) {
+    let mut optional = vec![];
+    if material.diffuse_texture.is_none() && material.normal_texture.is_none() {
+        optional.push("texcoord");
+    }
+    if material.normal_texture.is_none() {
+        optional.push("tangent");
+    }
+    for problem in shader.check_vertex_layout(layout, &optional) {
+        println!("Warning: mesh {}: {}", name, problem);
+    }
+}
+
+unsafe fn create_vao(shader: &shader::Shader, mesh: &mesh::Mesh) -> Result<gpu::VertexArray, String> {
+    // Pack every vertex attribute of the mesh into one interleaved buffer, laid out as the
+    // shaders expect
+    let vertices = mesh.vertex_buffer()?;
+    check_vertex_layout(shader, &mesh.name, &mesh.material, &vertices.layout);
+
     // Creating and setting up a Vertex Array Object
-    let mut vao_id: u32 = 0;
-    gl::GenVertexArrays(1, &mut vao_id);
-    gl::BindVertexArray(vao_id);
-
-    // Creating a Vertex Buffer Object
-    let mut vbo: u32 = 0;
-    gl::GenBuffers(1, &mut vbo);
-    gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
-    gl::BufferData(
-        gl::ARRAY_BUFFER,
-        byte_size_of_array(vertices),
-        pointer_to_array(vertices),
-        gl::STATIC_DRAW,
-    );
-
-    // Enabling the Vertex Attributes
-    let stride = 3 * size_of::<f32>();
-    gl::EnableVertexAttribArray(0);
-    gl::VertexAttribPointer(0, 3, gl::FLOAT, gl::FALSE, stride as i32, offset::<f32>(0));
+    let mut vao = gpu::VertexArray::new();
+    vao.bind();
+
+    // Creating a Vertex Buffer Object, and enabling the Vertex Attributes
+    vao.attach(vertices.upload());
 
     // Creating an Index Buffer Object
-    let mut ibo: u32 = 0;
-    gl::GenBuffers(1, &mut ibo);
-    gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, ibo);
-    gl::BufferData(
-        gl::ELEMENT_ARRAY_BUFFER,
-        byte_size_of_array(indices),
-        pointer_to_array(indices),
-        gl::STATIC_DRAW,
-    );
-
-    // Create a color buffer object
-    let mut cbo: u32 = 0;
-    gl::GenBuffers(1, &mut cbo);
-    gl::BindBuffer(gl::ARRAY_BUFFER, cbo);
-    gl::BufferData(
-        gl::ARRAY_BUFFER,
-        byte_size_of_array(colors),
-        pointer_to_array(colors),
-        gl::STATIC_DRAW,
-    );
-
-    // Enabling color attributes
-    gl::EnableVertexAttribArray(1);
-    gl::VertexAttribPointer(1, 4, gl::FLOAT, gl::FALSE, 0, ptr::null());
-
-    // Create a normal buffer object
-    let mut nbo: u32 = 0;
-    gl::GenBuffers(1, &mut nbo);
-    gl::BindBuffer(gl::ARRAY_BUFFER, nbo);
-    gl::BufferData(
-        gl::ARRAY_BUFFER,
-        byte_size_of_array(normals),
-        pointer_to_array(normals),
-        gl::STATIC_DRAW,
-    );
-
-    // Enable normal attribute (location = 2)
-    gl::EnableVertexAttribArray(2);
-    gl::VertexAttribPointer(2, 3, gl::FLOAT, gl::FALSE, 0, ptr::null());
-
-    return vao_id;
+    vao.attach(gpu::Buffer::with_data(gl::ELEMENT_ARRAY_BUFFER, &mesh.indices));
+
+    gl::BindVertexArray(0);
+    Ok(vao)
+}
+
+// Passes a material on to the `u_material` uniform of the fragment shader
+unsafe fn set_material(shader: &shader::Shader, material: &mesh::Material) {
+    shader.set_vec3("u_material.ambient", &material.ambient.into());
+    shader.set_vec3("u_material.diffuse", &material.diffuse.into());
+    shader.set_vec3("u_material.specular", &material.specular.into());
+    shader.set_f32("u_material.shininess", material.shininess);
+    shader.set_f32("u_material.dissolve", material.dissolve);
 }
 
 unsafe fn draw_scene(
-    node: &scene_graph::SceneNode,
+    graph: &SceneGraph,
+    node_id: NodeId,
     shader: &shader::Shader,
     view_projection_matrix: &glm::Mat4,
-    transformation_so_far: &glm::Mat4,
 ) {
-    
-    let mut local_transform = glm::translate(&glm::identity(), &node.position);
-
-    // Move pivot to reference point
-    local_transform = glm::translate(&local_transform, &node.reference_point);
-
-    // Apply rotations around Z, Y, X
-    local_transform = glm::rotate(&local_transform, node.rotation.z, &glm::vec3(0.0, 0.0, 1.0));
-    local_transform = glm::rotate(&local_transform, node.rotation.y, &glm::vec3(0.0, 1.0, 0.0));
-    local_transform = glm::rotate(&local_transform, node.rotation.x, &glm::vec3(1.0, 0.0, 0.0));
-
-    // Move pivot back
-    local_transform = glm::translate(&local_transform, &-node.reference_point);
-
-    // Combine model matrix with the scene's View Projection matrix
-    let model_matrix = transformation_so_far * local_transform;
-    let model_view_projection_matrix = view_projection_matrix * model_matrix;
-
-    shader.activate();
-    gl::UniformMatrix4fv(
-        shader.get_uniform_location("u_model_view_projection"),
-        1, gl::FALSE, model_view_projection_matrix.as_ptr(),
-    );
-    gl::UniformMatrix4fv(
-        shader.get_uniform_location("u_model"),
-        1, gl::FALSE, model_matrix.as_ptr(),
-    );
+    let node = &graph[node_id];
 
     // Check if node is drawable, if so: set uniforms, bind VAO and draw VAO
     if node.vao_id > 0 && node.index_count > 0 {
+        // Combine the (cached) model matrix with the scene's View Projection matrix
+        let model_matrix = graph.world_transform(node_id);
+        let model_view_projection_matrix = view_projection_matrix * model_matrix;
+
         shader.activate();
-        gl::UniformMatrix4fv(
-            shader.get_uniform_location("u_transform"),
-            1,
-            gl::FALSE,
-            model_view_projection_matrix.as_ptr(),
-        );
+        shader.set_mat4("u_model_view_projection", &model_view_projection_matrix);
+        shader.set_mat4("u_model", &model_matrix);
+        set_material(shader, &node.material);
+
+        // Sample the diffuse color from texture unit 0, if the node has a texture
+        shader.set_i32("u_hasDiffuseTexture", (node.texture_id > 0) as i32);
+        shader.set_i32("u_diffuseTexture", 0);
+        gl::ActiveTexture(gl::TEXTURE0);
+        gl::BindTexture(gl::TEXTURE_2D, node.texture_id);
+
+        // And the surface normal from texture unit 1, if the node has a normal map
+        shader.set_i32("u_hasNormalMap", (node.normal_map_id > 0) as i32);
+        shader.set_i32("u_normalMap", 1);
+        gl::ActiveTexture(gl::TEXTURE1);
+        gl::BindTexture(gl::TEXTURE_2D, node.normal_map_id);
+        gl::ActiveTexture(gl::TEXTURE0);
 
         gl::BindVertexArray(node.vao_id);
         gl::DrawElements(gl::TRIANGLES, node.index_count, gl::UNSIGNED_INT, ptr::null());
@@ -174,21 +158,191 @@ unsafe fn draw_scene(
     }
 
     // Recurse
-    for &child in &node.children {
-        draw_scene(&*child, shader, view_projection_matrix, &model_matrix);
+    for &child in node.children() {
+        draw_scene(graph, child, shader, view_projection_matrix);
+    }
+}
+
+// Sets up the GL state the scene is drawn with, once a context is current
+unsafe fn set_up_gl(capabilities: &capabilities::GlCapabilities) {
+    gl::Enable(gl::DEPTH_TEST);
+    gl::DepthFunc(gl::LESS);
+    gl::Enable(gl::CULL_FACE);
+    gl::Disable(gl::MULTISAMPLE);
+    gl::Enable(gl::BLEND);
+    gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+    // RUST_BACKTRACE also shows where the GL calls the driver complains about are made
+    if capabilities.debug_output {
+        gl_debug::install(gl_debug::DebugOutput::new().backtraces(std::env::var_os("RUST_BACKTRACE").is_some()));
+    } else {
+        println!("Warning: the driver has no debug output, so GL errors will go unnoticed");
+    }
+
+    // Print some diagnostics
+    println!("{}: {}", capabilities.vendor, capabilities.renderer);
+    println!("OpenGL\t: {}", capabilities.version);
+    println!("GLSL\t: {}", capabilities.glsl_version);
+}
+
+// Builds the shaders, and loads the lunar surface and its helicopters
+fn load_scene(capabilities: &capabilities::GlCapabilities) -> Result<(shader::Shader, scene_file::Scene), String> {
+    let simple_shader = unsafe {
+        shader::ShaderBuilder::new()
+            .attach_file("./shaders/simple.vert")
+            .and_then(|builder| builder.attach_file("./shaders/simple.frag"))
+            .and_then(|builder| builder.link())
+    }.map_err(|e| e.to_string())?;
+    if simple_shader.warnings().count() > 0 {
+        for diagnostics in simple_shader.diagnostics() {
+            println!("{}", diagnostics);
+        }
     }
+
+    // Load the lunar surface and its helicopters, creating a VAO for every mesh in the scene
+    let scene = scene_file::Scene::load("./resources/lunar_scene.ron",
+        |mesh| unsafe {
+            create_vao(&simple_shader, mesh)
+        },
+        |path| unsafe {
+            let options = texture::TextureOptions {
+                anisotropy: capabilities.max_anisotropy.unwrap_or(1.0).min(8.0),
+                ..texture::TextureOptions::default()
+            };
+            let texture = texture::Texture::load(path, &options)?;
+            println!("Loaded texture {} ({}x{})", path, texture.width, texture.height);
+            Ok(texture.texture)
+        },
+    ).map_err(|e| format!("Failed to load scene: {}", e))?;
+    println!("GPU objects: {}", gpu::live_counts());
+
+    Ok((simple_shader, scene))
+}
+
+// Draws the scene into the bound framebuffer, as seen from `cam_pos`
+unsafe fn render_frame(
+    shader: &shader::Shader,
+    scene: &scene_file::Scene,
+    view_projection: &glm::Mat4,
+    cam_pos: &glm::Vec3,
+) {
+    // Free whatever GPU objects were dropped since the last frame
+    gpu::delete_pending();
+
+    gl::ClearColor(0.035, 0.046, 0.078, 1.0); // night sky
+    //gl::ClearColor(1.0, 0.0, 1.0, 1.0); // magenta
+    gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+
+    shader.activate();
+    shader.set_vec3("u_lightPos", &glm::vec3(0.8, -0.5, 0.6));
+
+    shader.set_vec3("u_viewPos", cam_pos);
+
+    draw_scene(&scene.graph, scene.graph.root(), shader, view_projection);
+}
+
+fn projection_matrix(aspect_ratio: f32) -> glm::Mat4 {
+    const FOVY_RAD: f32 = std::f32::consts::FRAC_PI_4;
+    glm::perspective(aspect_ratio, FOVY_RAD, 1.0, 2000.0)
+}
+
+// Renders frames into a framebuffer without opening a window, a fixed step of scene time apart,
+// from where the camera starts out in the window
+fn run_headless(options: &cli::Options, (width, height): (u32, u32)) -> Result<(), String> {
+    let context = unsafe { headless::HeadlessContext::new(4, 3)? };
+    gl::load_with(|symbol| context.get_proc_address(symbol));
+    let capabilities = unsafe { capabilities::GlCapabilities::query() };
+    if options.gl_info {
+        println!("{}", capabilities.to_json());
+        return Ok(());
+    }
+    unsafe { set_up_gl(&capabilities) };
+    println!("Rendering {} frames of {}x{} offscreen ({} EGL context)", options.frames, width, height, context.platform());
+
+    let framebuffer = unsafe { gpu::Framebuffer::new(width, height)? };
+    unsafe { framebuffer.bind() };
+    let aspect_ratio = width as f32 / height as f32;
+
+    // Draws a frame, given the scene time and the time since the previous frame
+    let mut draw: Box<dyn FnMut(f32, f32)> = match options.scene {
+        cli::SceneName::Lunar => {
+            let (shader, mut scene) = load_scene(&capabilities)?;
+            let cam_pos = glm::vec3(0.0, 0.0, 0.0);
+            let view = glm::look_at(&cam_pos, &glm::vec3(0.0, 0.0, -1.0), &glm::vec3(0.0, 1.0, 0.0));
+            let projection = projection_matrix(aspect_ratio);
+            Box::new(move |elapsed, delta_time| {
+                scene.animate(elapsed, delta_time);
+                unsafe { render_frame(&shader, &scene, &(projection * view), &cam_pos) };
+            })
+        }
+        cli::SceneName::Circle => {
+            let circle = unsafe { assignment_scenes::Circle::new()? };
+            Box::new(move |elapsed, _| unsafe { circle.draw(elapsed) })
+        }
+        cli::SceneName::Billboards => {
+            let billboards = unsafe { assignment_scenes::Billboards::new()? };
+            Box::new(move |elapsed, _| unsafe { billboards.draw(elapsed, aspect_ratio) })
+        }
+    };
+
+    let frame_time = options.frame_time();
+    let mut recorder = options.record.as_deref().map(capture::Recorder::new).transpose()?;
+    for frame in 0..options.frames {
+        draw(frame as f32 * frame_time, frame_time);
+        unsafe { gl::Finish() };
+        gl_debug::check();
+        if let Some(recorder) = &mut recorder {
+            recorder.save(&unsafe { capture::read_framebuffer(&framebuffer) })?;
+        }
+    }
+    println!("Rendered {} frames", options.frames);
+    if let Some(recorder) = &recorder {
+        println!("Saved {} frames into {}", recorder.frames(), options.record.as_ref().unwrap().display());
+    }
+    if let Some(path) = &options.screenshot {
+        capture::save_png(&unsafe { capture::read_framebuffer(&framebuffer) }, path)?;
+        println!("Saved screenshot {}", path.display());
+    }
+    Ok(())
 }
 
 fn main() {
+    let options = cli::Options::parse(std::env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("{}\n\n{}", e, cli::USAGE);
+        std::process::exit(2);
+    });
+    if options.help {
+        println!("{}", cli::USAGE);
+        return;
+    }
+    if let Some(size) = options.headless {
+        if let Err(e) = run_headless(&options, size) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     // Set up the necessary objects to deal with windows and event handling
     let el = glutin::event_loop::EventLoop::new();
     let wb = glutin::window::WindowBuilder::new()
         .with_title("Gloom-rs")
         .with_resizable(true)
+        .with_visible(!options.gl_info)
         .with_inner_size(glutin::dpi::LogicalSize::new(INITIAL_SCREEN_W, INITIAL_SCREEN_H));
     let cb = glutin::ContextBuilder::new()
         .with_vsync(true);
     let windowed_context = cb.build_windowed(wb, &el).unwrap();
+
+    // Only the context is needed for this, so the window is never shown
+    if options.gl_info {
+        let capabilities = unsafe {
+            let context = windowed_context.make_current().unwrap();
+            gl::load_with(|symbol| context.get_proc_address(symbol) as *const _);
+            capabilities::GlCapabilities::query()
+        };
+        println!("{}", capabilities.to_json());
+        return;
+    }
     // Uncomment these if you want to use the mouse for controls, but want it to be confined to the screen and/or invisible.
     // windowed_context.window().set_cursor_grab(true).expect("failed to grab cursor");
     // windowed_context.window().set_cursor_visible(false);
@@ -221,124 +375,16 @@ fn main() {
 
         let mut window_aspect_ratio = INITIAL_SCREEN_W as f32 / INITIAL_SCREEN_H as f32;
 
-        // Set up openGL
-        unsafe {
-            gl::Enable(gl::DEPTH_TEST);
-            gl::DepthFunc(gl::LESS);
-            gl::Enable(gl::CULL_FACE);
-            gl::Disable(gl::MULTISAMPLE);
-            gl::Enable(gl::BLEND);
-            gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
-            gl::Enable(gl::DEBUG_OUTPUT_SYNCHRONOUS);
-            gl::DebugMessageCallback(Some(util::debug_callback), ptr::null());
-
-            // Print some diagnostics
-            println!("{}: {}", util::get_gl_string(gl::VENDOR), util::get_gl_string(gl::RENDERER));
-            println!("OpenGL\t: {}", util::get_gl_string(gl::VERSION));
-            println!("GLSL\t: {}", util::get_gl_string(gl::SHADING_LANGUAGE_VERSION));
-        }
+        let capabilities = unsafe { capabilities::GlCapabilities::query() };
+        unsafe { set_up_gl(&capabilities) };
 
-        let simple_shader = unsafe {
-            shader::ShaderBuilder::new()
-                .attach_file("./shaders/simple.vert")
-                .attach_file("./shaders/simple.frag")
-                .link()
-        };
+        let (mut simple_shader, mut scene) = load_scene(&capabilities).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        });
 
-        // let u_transform_loc = unsafe {
-        //     let name = std::ffi::CString::new("u_transform").unwrap();
-        //     gl::GetUniformLocation(simple_shader.program_id, name.as_ptr())
-        // };
-
-        // Load the terrain mesh from file
-        let terrain = mesh::Terrain::load("./resources/lunarsurface.obj");
-
-        // Terrain VAO
-        let terrain_vao = unsafe { create_vao(&terrain.vertices, &terrain.indices, &terrain.colors, &terrain.normals) };
-        let terrain_index_count = terrain.index_count;
-
-        // Load the helicopter mesh from file
-        let helicopter = mesh::Helicopter::load("./resources/helicopter.obj");
-
-        // Create VAOs for each part
-        let body_vao = unsafe { create_vao(&helicopter.body.vertices, &helicopter.body.indices, &helicopter.body.colors, &helicopter.body.normals) };
-        let door_vao = unsafe { create_vao(&helicopter.door.vertices, &helicopter.door.indices, &helicopter.door.colors, &helicopter.door.normals) };
-        let main_rotor_vao = unsafe { create_vao(&helicopter.main_rotor.vertices, &helicopter.main_rotor.indices, &helicopter.main_rotor.colors, &helicopter.main_rotor.normals) };
-        let tail_rotor_vao = unsafe { create_vao(&helicopter.tail_rotor.vertices, &helicopter.tail_rotor.indices, &helicopter.tail_rotor.colors, &helicopter.tail_rotor.normals) };
-
-        // Index counts for each part
-        let body_index_count       = helicopter.body.index_count;
-        let door_index_count       = helicopter.door.index_count;
-        let main_rotor_index_count = helicopter.main_rotor.index_count;
-        let tail_rotor_index_count = helicopter.tail_rotor.index_count;
-
-        // Root node for scene
-        let mut scene_root = SceneNode::new();
-
-        // Terrain node
-        let mut terrain_node = SceneNode::from_vao(terrain_vao, terrain_index_count);
-        scene_root.add_child(&terrain_node);
-
-
-        // // Helicopter root node
-        // let mut helicopter_root = SceneNode::new();
-        // terrain_node.add_child(&helicopter_root);
-
-        // // Helicopter nodes
-        // let mut body_node = SceneNode::from_vao(body_vao, body_index_count);
-        // let mut door_node = SceneNode::from_vao(door_vao, door_index_count);
-        // let mut main_rotor_node = SceneNode::from_vao(main_rotor_vao, main_rotor_index_count);
-        // let mut tail_rotor_node = SceneNode::from_vao(tail_rotor_vao, tail_rotor_index_count);
-
-        // helicopter_root.add_child(&body_node);
-        // helicopter_root.add_child(&door_node);
-        // helicopter_root.add_child(&main_rotor_node);
-        // helicopter_root.add_child(&tail_rotor_node);
-
-        // helicopter_root.print();
-
-        // // Setting reference points for helicopter parts
-        // body_node.reference_point = glm::vec3(0.0, 0.0, 0.0);
-        // door_node.reference_point = glm::vec3(0.0, 0.0, 0.0);
-        // main_rotor_node.reference_point = glm::vec3(0.0, 0.0, 0.0);
-        // tail_rotor_node.reference_point = glm::vec3(0.35, 2.3, 10.4);
-
-        const NUMBER_OF_HELICOPTERS: usize = 5;
-
-        // Store handles to helicopter nodes
-        let mut helicopter_roots: Vec<scene_graph::Node> = Vec::with_capacity(NUMBER_OF_HELICOPTERS);
-        let mut main_rotor_nodes: Vec<scene_graph::Node> = Vec::with_capacity(NUMBER_OF_HELICOPTERS);
-        let mut tail_rotor_nodes: Vec<scene_graph::Node> = Vec::with_capacity(NUMBER_OF_HELICOPTERS);
-        let mut door_nodes: Vec<scene_graph::Node> = Vec::with_capacity(NUMBER_OF_HELICOPTERS);
-
-        for _ in 0..NUMBER_OF_HELICOPTERS {
-            // Helicopter root node
-            let mut helicopter_root = SceneNode::new();
-            terrain_node.add_child(&helicopter_root);
-
-            // Helicopter parts
-            let mut body_node = SceneNode::from_vao(body_vao, body_index_count);
-            let mut door_node = SceneNode::from_vao(door_vao, door_index_count);
-            let mut main_rotor_node = SceneNode::from_vao(main_rotor_vao, main_rotor_index_count);
-            let mut tail_rotor_node = SceneNode::from_vao(tail_rotor_vao, tail_rotor_index_count);
-
-            helicopter_root.add_child(&body_node);
-            helicopter_root.add_child(&door_node);
-            helicopter_root.add_child(&main_rotor_node);
-            helicopter_root.add_child(&tail_rotor_node);
-
-            // Reference points
-            body_node.reference_point = glm::vec3(0.0, 0.0, 0.0);
-            door_node.reference_point = glm::vec3(0.0, 0.0, 0.0);
-            main_rotor_node.reference_point = glm::vec3(0.0, 0.0, 0.0);
-            tail_rotor_node.reference_point = glm::vec3(0.35, 2.3, 10.4);
-
-            // Save nodes for later animation
-            helicopter_roots.push(helicopter_root);
-            main_rotor_nodes.push(main_rotor_node);
-            tail_rotor_nodes.push(tail_rotor_node);
-            door_nodes.push(door_node);
-        }
+        // Look up the doors once, rather than every frame
+        let door_nodes = scene.graph.find_all("**/door");
 
 
 
@@ -355,17 +401,50 @@ fn main() {
         let pitch_limit: f32 = std::f32::consts::FRAC_PI_2 - 0.01;
 
 
+        // Frames to save. A screenshot given on the command line is taken of the first frame.
+        let mut recorder = options.record.as_deref()
+            .map(capture::Recorder::new)
+            .transpose()
+            .unwrap_or_else(|e| panic!("{}", e));
+        let mut screenshot_path = options.screenshot.clone();
+        let mut screenshot_key_down = false;
+        let mut frame: u32 = 0;
+
         // The main rendering loop
-        let first_frame_time = std::time::Instant::now();
+        let mut first_frame_time = std::time::Instant::now();
         let mut previous_frame_time = first_frame_time;
+        let mut last_shader_check = first_frame_time;
         loop {
 
             // Compute time passed since the previous frame and since the start of the program
             let now = std::time::Instant::now();
-            let elapsed = now.duration_since(first_frame_time).as_secs_f32();
-            let delta_time = now.duration_since(previous_frame_time).as_secs_f32();
+            let mut elapsed = now.duration_since(first_frame_time).as_secs_f32();
+            let mut delta_time = now.duration_since(previous_frame_time).as_secs_f32();
             previous_frame_time = now;
 
+            // While recording, the scene moves on by a fixed step every frame, however long
+            // saving the frames takes
+            if recorder.is_some() {
+                elapsed = frame as f32 * options.frame_time();
+                delta_time = options.frame_time();
+            }
+
+            // Pick up edits to the shader files without restarting
+            if now.duration_since(last_shader_check).as_secs_f32() > 0.5 {
+                last_shader_check = now;
+                match unsafe { simple_shader.reload_if_changed() } {
+                    Some(Ok(())) => {
+                        println!("Reloaded shaders.");
+                        // The new shaders may read the vertices differently
+                        for (name, material, layout) in scene.vertex_layouts() {
+                            check_vertex_layout(&simple_shader, &name, material, layout);
+                        }
+                    }
+                    Some(Err(e)) => println!("{}\nKeeping the previous shaders.", e.to_string().trim_end()),
+                    None => {}
+                }
+            }
+
             // Handle resize events
             if let Ok(mut new_size) = window_size.lock() {
                 if new_size.2 {
@@ -429,16 +508,16 @@ fn main() {
 
                         // Open/close door (X/Z)
                         VirtualKeyCode::X => {
-                        for door in &mut door_nodes {
-                            if door.position.z < 2.0 {
-                                door.position.z += 0.2;
+                        for &door in &door_nodes {
+                            if scene.graph[door].position.z < 2.0 {
+                                scene.graph[door].position.z += 0.2;
                             }
                         }
                         }
                         VirtualKeyCode::Z => {
-                        for door in &mut door_nodes {
-                            if door.position.z > 0.0 {
-                                door.position.z -= 0.2;   
+                        for &door in &door_nodes {
+                            if scene.graph[door].position.z > 0.0 {
+                                scene.graph[door].position.z -= 0.2;
                             }
                         }
                         }
@@ -449,6 +528,13 @@ fn main() {
                 }
 
                 cam_pitch = cam_pitch.clamp(-pitch_limit, pitch_limit);
+
+                // Take a screenshot when F12 goes down, rather than every frame it is held
+                let screenshot_key = keys.contains(&VirtualKeyCode::F12);
+                if screenshot_key && !screenshot_key_down {
+                    screenshot_path = Some(capture::screenshot_path());
+                }
+                screenshot_key_down = screenshot_key;
             }
 
 
@@ -465,13 +551,7 @@ fn main() {
 
             // let model: glm::Mat4 = glm::translate(&glm::identity(), &glm::vec3(0.0, 0.0, -2.0));
 
-            const FOVY_RAD: f32 = std::f32::consts::FRAC_PI_4;
-            let projection: glm::Mat4 = glm::perspective(
-                window_aspect_ratio,
-                FOVY_RAD,
-                1.0,
-                2000.0,
-            );
+            let projection = projection_matrix(window_aspect_ratio);
 
             // let transform: glm::Mat4 = projection * view * model;
 
@@ -495,68 +575,30 @@ fn main() {
             // // Spin the tail rotor
             // tail_rotor_node.rotation.x += 15.0 * delta_time;
 
-            // Animate all helicopters
-            for (i, root) in helicopter_roots.iter_mut().enumerate() {
-                let heading = simple_heading_animation(elapsed + i as f32 * 0.75);
+            // Fly the helicopters and spin their rotors
+            scene.animate(elapsed, delta_time);
 
-                root.position.x = heading.x;
-                root.position.y = 5.0;
-                root.position.z = heading.z;
+            unsafe { render_frame(&simple_shader, &scene, &(projection * view), &cam_pos) };
 
-                root.rotation.z = heading.roll;
-                root.rotation.y = heading.yaw;
-                root.rotation.x = heading.pitch;
-            }
+            gl_debug::check();
 
-            // Spin all rotors
-            for main_rotor in &mut main_rotor_nodes {
-                main_rotor.rotation.y += 10.0 * delta_time;
-            }
-            for tail_rotor in &mut tail_rotor_nodes {
-                tail_rotor.rotation.x += 15.0 * delta_time;
+            // Read the frame back before it is swapped away
+            if let Some(path) = screenshot_path.take() {
+                match capture::save_png(&unsafe { capture::read_viewport() }, &path) {
+                    Ok(()) => println!("Saved screenshot {}", path.display()),
+                    Err(e) => println!("{}", e),
+                }
             }
-
-            unsafe {
-                gl::ClearColor(0.035, 0.046, 0.078, 1.0); // night sky
-                //gl::ClearColor(1.0, 0.0, 1.0, 1.0); // magenta
-                gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
-
-                simple_shader.activate();
-                // gl::UniformMatrix4fv(u_transform_loc, 1, gl::FALSE, transform.as_ptr());
-
-                // gl::BindVertexArray(terrain_vao);
-                // gl::DrawElements(
-                //     gl::TRIANGLES,
-                //     terrain_index_count,
-                //     gl::UNSIGNED_INT,
-                //     std::ptr::null(),
-                // );
-
-                // gl::BindVertexArray(body_vao);
-                // gl::DrawElements(gl::TRIANGLES, body_index_count, gl::UNSIGNED_INT, ptr::null());
-
-                // gl::BindVertexArray(door_vao);
-                // gl::DrawElements(gl::TRIANGLES, door_index_count, gl::UNSIGNED_INT, ptr::null());
-
-                // gl::BindVertexArray(main_rotor_vao);
-                // gl::DrawElements(gl::TRIANGLES, main_rotor_index_count, gl::UNSIGNED_INT, ptr::null());
-
-                // gl::BindVertexArray(tail_rotor_vao);
-                // gl::DrawElements(gl::TRIANGLES, tail_rotor_index_count, gl::UNSIGNED_INT, ptr::null());
-
-                // gl::BindVertexArray(0);
-                
-
-                let u_light_pos_loc = simple_shader.get_uniform_location("u_lightPos");
-                gl::Uniform3f(u_light_pos_loc, 0.8, -0.5, 0.6);
-
-                let u_view_pos_loc = simple_shader.get_uniform_location("u_viewPos");
-                gl::Uniform3f(u_view_pos_loc, cam_pos.x, cam_pos.y, cam_pos.z);
-
-                let view_projection = projection * view;
-                let identity = glm::identity::<f32, 4>();
-                draw_scene(&scene_root, &simple_shader, &view_projection, &identity);
+            let recorded = recorder.as_mut().map(|recorder| recorder.save(&unsafe { capture::read_viewport() }));
+            if let Some(Err(e)) = recorded {
+                println!("{}\nStopped recording.", e);
+                recorder = None;
+
+                // Carry on from the recorded time, rather than jumping ahead to the clock
+                let recorded_time = std::time::Duration::from_secs_f32(elapsed);
+                first_frame_time = now.checked_sub(recorded_time).unwrap_or(now);
             }
+            frame += 1;
 
             // Display the new color buffer on the display
             context.swap_buffers().unwrap(); // we use "double buffering" to avoid artifacts
@@ -1,18 +1,95 @@
+extern crate nalgebra_glm as glm;
+
+use std::collections::HashMap;
+use serde::{Serialize, Deserialize};
 use tobj;
 
+use crate::vertex::{ComponentType, VertexBuffer, VertexLayout};
+
 // internal helper
 fn generate_color_vec(color: [f32; 4], num: usize) -> Vec<f32> {
     color.iter().cloned().cycle().take(num*4).collect()
 }
 
+// Material
+
+// How a surface reflects light, as read from an MTL file. The colors are multiplied with the
+// vertex colors of the mesh, so a material can be combined with a flat color picked in code.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Material {
+    pub ambient         : [f32; 3],        // Ka
+    pub diffuse         : [f32; 3],        // Kd
+    pub specular        : [f32; 3],        // Ks
+    pub shininess       : f32,             // Ns
+    pub dissolve        : f32,             // d, where 1.0 is fully opaque
+    pub diffuse_texture : Option<String>,  // map_Kd
+    pub normal_texture  : Option<String>,  // map_Bump, a tangent space normal map
+}
+
+impl Default for Material {
+    // The look every mesh had before materials were read from file
+    fn default() -> Self {
+        Material {
+            ambient         : [0.2, 0.2, 0.2],
+            diffuse         : [0.7, 0.7, 0.7],
+            specular        : [0.5, 0.5, 0.5],
+            shininess       : 32.0,
+            dissolve        : 1.0,
+            diffuse_texture : None,
+            normal_texture  : None,
+        }
+    }
+}
+
+impl From<&tobj::Material> for Material {
+    fn from(material: &tobj::Material) -> Self {
+        let default = Material::default();
+        Material {
+            ambient         : material.ambient.unwrap_or(default.ambient),
+            diffuse         : material.diffuse.unwrap_or(default.diffuse),
+            specular        : material.specular.unwrap_or(default.specular),
+            shininess       : material.shininess.unwrap_or(default.shininess),
+            dissolve        : material.dissolve.unwrap_or(default.dissolve),
+            diffuse_texture : material.diffuse_texture.clone(),
+            normal_texture  : material.normal_texture.clone(),
+        }
+    }
+}
+
+// How normals are generated for a mesh. Faces meeting at a shared corner have their normals
+// averaged, weighted by the angle each face makes at that corner, as long as the faces are bent
+// less than the crease angle (in radians) away from each other. Corners on either side of a sharper
+// crease are split into separate vertices. Smooth and flat are the two extremes of this.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum NormalMode {
+    Smooth,
+    Flat,
+    Crease(f32),
+}
+
 // Mesh
 
+// Where each vertex attribute of a mesh is fed to the shaders, matching the `layout(location = ...)`
+// declarations in simple.vert. A new attribute needs a row here and a line in the shader.
+const VERTEX_ATTRIBUTES: [(&str, u32, usize); 5] = [
+    ("position", 0, 3),
+    ("color",    1, 4),
+    ("normal",   2, 3),
+    ("texcoord", 3, 2),
+    ("tangent",  4, 4),
+];
+
+#[derive(Clone)]
 pub struct Mesh {
+    pub name        : String,
     pub vertices    : Vec<f32>,
     pub normals     : Vec<f32>,
+    pub texcoords   : Vec<f32>,
+    pub tangents    : Vec<f32>,   // xyz along increasing u, w is the handedness of the bitangent
     pub colors      : Vec<f32>,
     pub indices     : Vec<u32>,
     pub index_count : i32,
+    pub material    : Material,
 }
 
 impl Mesh {
@@ -20,105 +97,557 @@ impl Mesh {
         let num_verts = mesh.positions.len() / 3;
         let index_count = mesh.indices.len() as i32;
         Mesh {
+            name: String::new(),
             vertices: mesh.positions,
             normals: mesh.normals,
+            texcoords: mesh.texcoords,
+            tangents: vec![],
             indices: mesh.indices,
             colors: generate_color_vec(color, num_verts),
             index_count,
+            material: Material::default(),
+        }
+    }
+
+    pub fn vertex_count(&self) -> usize {
+        self.vertices.len() / 3
+    }
+
+    fn attribute_values(&self, name: &str) -> &Vec<f32> {
+        match name {
+            "position" => &self.vertices,
+            "color"    => &self.colors,
+            "normal"   => &self.normals,
+            "texcoord" => &self.texcoords,
+            "tangent"  => &self.tangents,
+            _ => unreachable!("{} is not a mesh attribute", name),
+        }
+    }
+
+    // The attributes this mesh has values for, left out attributes keep the shader's default
+    pub fn vertex_layout(&self) -> VertexLayout {
+        VERTEX_ATTRIBUTES.iter()
+            .filter(|(name, _, _)| !self.attribute_values(name).is_empty())
+            .fold(VertexLayout::new(), |layout, &(name, location, components)| {
+                layout.with(name, location, components, ComponentType::Float, false)
+            })
+    }
+
+    // Packs every attribute of the mesh into one interleaved buffer
+    pub fn vertex_buffer(&self) -> Result<VertexBuffer, String> {
+        let layout = self.vertex_layout();
+        let names: Vec<String> = layout.attributes().iter().map(|a| a.name.clone()).collect();
+        names.iter()
+            .fold(VertexBuffer::builder(layout, self.vertex_count()), |builder, name| {
+                builder.attribute(name, self.attribute_values(name))
+            })
+            .build()
+            .map_err(|e| format!("Cannot pack the vertices of {}: {}", self.name, e))
+    }
+
+    // Checks that every vertex attribute has one entry per vertex, and that every index points at
+    // a vertex. Normals, colors, texture coordinates and tangents may also be missing altogether.
+    pub fn validate(&self) -> Result<(), String> {
+        let num_verts = self.vertex_count();
+        let mut problems = vec![];
+        if !self.vertices.len().is_multiple_of(3) {
+            problems.push(format!("{} position components is not a multiple of 3", self.vertices.len()));
+        }
+        let attributes = [("normal", &self.normals, 3), ("color", &self.colors, 4), ("texture coordinate", &self.texcoords, 2), ("tangent", &self.tangents, 4)];
+        for (name, values, components) in attributes {
+            if !values.is_empty() && values.len() != num_verts * components {
+                problems.push(format!("{} {} components for {} vertices, expected {}", values.len(), name, num_verts, num_verts * components));
+            }
+        }
+        if !self.indices.len().is_multiple_of(3) {
+            problems.push(format!("{} indices do not make up whole triangles", self.indices.len()));
+        }
+        if let Some(&index) = self.indices.iter().find(|&&i| i as usize >= num_verts) {
+            problems.push(format!("index {} is out of range for {} vertices", index, num_verts));
         }
+        if self.index_count != self.indices.len() as i32 {
+            problems.push(format!("index count {} does not match the {} indices", self.index_count, self.indices.len()));
+        }
+
+        if problems.is_empty() { Ok(()) } else { Err(problems.join("; ")) }
+    }
+
+    fn position(&self, vertex: u32) -> glm::Vec3 {
+        let i = vertex as usize * 3;
+        glm::vec3(self.vertices[i], self.vertices[i + 1], self.vertices[i + 2])
+    }
+
+    // The angle a triangle makes at one of its corners, zero for degenerate triangles
+    fn corner_angle(&self, triangle: [u32; 3], corner: usize) -> f32 {
+        let p = self.position(triangle[corner]);
+        let to_next = self.position(triangle[(corner + 1) % 3]) - p;
+        let to_prev = self.position(triangle[(corner + 2) % 3]) - p;
+        if to_next.norm() > 0.0 && to_prev.norm() > 0.0 {
+            glm::angle(&to_next, &to_prev)
+        } else {
+            0.0
+        }
+    }
+
+    // Replaces the normals of the mesh, splitting vertices along creases where needed. Vertices
+    // sharing a position are treated as the same corner, even if they differ in color or
+    // texture coordinates.
+    pub fn generate_normals(&mut self, mode: NormalMode) {
+        let min_cos = match mode {
+            NormalMode::Smooth => -1.0,
+            NormalMode::Flat => 1.0 - 1e-5,
+            NormalMode::Crease(angle) => angle.cos(),
+        };
+
+        let triangles: Vec<[u32; 3]> = self.indices.chunks_exact(3).map(|t| [t[0], t[1], t[2]]).collect();
+        let face_normals: Vec<glm::Vec3> = triangles.iter().map(|t| {
+            let [a, b, c] = t.map(|v| self.position(v));
+            let normal = glm::cross(&(b - a), &(c - a));
+            if normal.norm() > 0.0 { normal.normalize() } else { glm::zero() }
+        }).collect();
+
+        // Each face touching a position, with the angle it makes there
+        let position_key = |v: glm::Vec3| [v.x.to_bits(), v.y.to_bits(), v.z.to_bits()];
+        let mut faces_at: HashMap<[u32; 3], Vec<(usize, f32)>> = HashMap::new();
+        for (face, triangle) in triangles.iter().enumerate() {
+            for corner in 0..3 {
+                let angle = self.corner_angle(*triangle, corner);
+                faces_at.entry(position_key(self.position(triangle[corner]))).or_default().push((face, angle));
+            }
+        }
+
+        // Every corner gets its own normal, and the vertices are rebuilt from the distinct
+        // (vertex, normal) pairs
+        let mut new_index_of: HashMap<(u32, [u32; 3]), u32> = HashMap::new();
+        let mut source_vertex: Vec<u32> = vec![];
+        let mut normals: Vec<f32> = vec![];
+        let mut indices: Vec<u32> = Vec::with_capacity(self.indices.len());
+        for (face, triangle) in triangles.iter().enumerate() {
+            for &vertex in triangle {
+                let own = face_normals[face];
+                let mut normal: glm::Vec3 = glm::zero();
+                for &(other, angle) in &faces_at[&position_key(self.position(vertex))] {
+                    if glm::dot(&face_normals[other], &own) >= min_cos {
+                        normal += face_normals[other] * angle;
+                    }
+                }
+                let normal = if normal.norm() > 1e-12 { normal.normalize() } else { own };
+
+                let key = (vertex, position_key(normal));
+                let index = *new_index_of.entry(key).or_insert_with(|| {
+                    source_vertex.push(vertex);
+                    normals.extend_from_slice(normal.as_slice());
+                    source_vertex.len() as u32 - 1
+                });
+                indices.push(index);
+            }
+        }
+
+        // Carry every other attribute over to the (possibly) duplicated vertices
+        let gather = |values: &Vec<f32>, components: usize| -> Vec<f32> {
+            if values.is_empty() {
+                return vec![];
+            }
+            source_vertex.iter()
+                .flat_map(|&v| values[v as usize * components..(v as usize + 1) * components].iter().copied())
+                .collect()
+        };
+        self.vertices  = gather(&self.vertices, 3);
+        self.colors    = gather(&self.colors, 4);
+        self.texcoords = gather(&self.texcoords, 2);
+        self.normals   = normals;
+        self.indices   = indices;
+        self.index_count = self.indices.len() as i32;
+        // Tangents were made against the old normals
+        self.tangents.clear();
+    }
+
+    // Replaces the tangents of the mesh, the way Lengyel describes: each triangle's tangent and
+    // bitangent are worked out from its texture coordinates, projected onto the plane of each
+    // vertex normal and summed up per vertex, weighted by corner angle. The sum is then made
+    // orthogonal to the normal again, and the w component records whether the bitangent is
+    // cross(normal, tangent) or its opposite. This is not MikkTSpace, so normal maps baked by
+    // other tools can look slightly off along seams. The shader is expected to rebuild the
+    // bitangent as w * cross(normal, tangent) rather than interpolate it.
+    pub fn generate_tangents(&mut self) -> Result<(), String> {
+        if self.texcoords.is_empty() {
+            return Err(format!("{} has no texture coordinates to generate tangents from", self.name));
+        }
+        if self.normals.is_empty() {
+            return Err(format!("{} needs normals before tangents can be generated", self.name));
+        }
+        self.validate()?;
+
+        let normal_of = |v: usize| glm::vec3(self.normals[v * 3], self.normals[v * 3 + 1], self.normals[v * 3 + 2]);
+        let texcoord_of = |v: u32| glm::vec2(self.texcoords[v as usize * 2], self.texcoords[v as usize * 2 + 1]);
+        let in_plane_of = |v: glm::Vec3, normal: &glm::Vec3| {
+            let projected = v - normal * glm::dot(normal, &v);
+            if projected.norm() > 1e-12 { projected.normalize() } else { glm::zero() }
+        };
+
+        let num_verts = self.vertex_count();
+        let mut tangent_sums: Vec<glm::Vec3> = vec![glm::zero(); num_verts];
+        let mut bitangent_sums: Vec<glm::Vec3> = vec![glm::zero(); num_verts];
+        for t in self.indices.chunks_exact(3) {
+            let triangle = [t[0], t[1], t[2]];
+            let edge_1 = self.position(triangle[1]) - self.position(triangle[0]);
+            let edge_2 = self.position(triangle[2]) - self.position(triangle[0]);
+            let delta_1 = texcoord_of(triangle[1]) - texcoord_of(triangle[0]);
+            let delta_2 = texcoord_of(triangle[2]) - texcoord_of(triangle[0]);
+            let determinant = delta_1.x * delta_2.y - delta_2.x * delta_1.y;
+            if determinant.abs() < 1e-12 {
+                // The texture is not stretched over this triangle, so it has no direction to give
+                continue;
+            }
+            let tangent = (edge_1 * delta_2.y - edge_2 * delta_1.y) / determinant;
+            let bitangent = (edge_2 * delta_1.x - edge_1 * delta_2.x) / determinant;
+
+            for corner in 0..3 {
+                let vertex = triangle[corner] as usize;
+                let normal = normal_of(vertex);
+                let angle = self.corner_angle(triangle, corner);
+                tangent_sums[vertex] += in_plane_of(tangent, &normal) * angle;
+                bitangent_sums[vertex] += in_plane_of(bitangent, &normal) * angle;
+            }
+        }
+
+        let mut tangents = Vec::with_capacity(num_verts * 4);
+        for vertex in 0..num_verts {
+            let normal = normal_of(vertex);
+            let mut tangent = in_plane_of(tangent_sums[vertex], &normal);
+            if tangent.norm() == 0.0 {
+                // Any direction along the surface will do where the texture gives none
+                let axis = if normal.x.abs() < 0.9 { glm::vec3(1.0, 0.0, 0.0) } else { glm::vec3(0.0, 1.0, 0.0) };
+                tangent = in_plane_of(axis, &normal);
+            }
+            let handedness = if glm::dot(&glm::cross(&normal, &tangent), &bitangent_sums[vertex]) < 0.0 { -1.0 } else { 1.0 };
+            tangents.extend_from_slice(&[tangent.x, tangent.y, tangent.z, handedness]);
+        }
+        self.tangents = tangents;
+        Ok(())
+    }
+
+    // Paints every vertex in the same color
+    pub fn set_color(&mut self, color: [f32; 4]) {
+        self.colors = generate_color_vec(color, self.vertex_count());
+    }
+
+    // Joins several meshes into one, shifting the indices of each to point past the vertices of
+    // those before it. A mesh can only have one material, so the first mesh decides it.
+    pub fn merge(name: &str, meshes: &[Mesh]) -> Mesh {
+        let mut merged = Mesh {
+            name: name.to_string(),
+            vertices: vec![],
+            normals: vec![],
+            texcoords: vec![],
+            tangents: vec![],
+            colors: vec![],
+            indices: vec![],
+            index_count: 0,
+            material: meshes.first().map(|m| m.material.clone()).unwrap_or_default(),
+        };
+        for mesh in meshes {
+            let offset = merged.vertex_count() as u32;
+            merged.vertices.extend_from_slice(&mesh.vertices);
+            merged.normals.extend_from_slice(&mesh.normals);
+            merged.texcoords.extend_from_slice(&mesh.texcoords);
+            merged.tangents.extend_from_slice(&mesh.tangents);
+            merged.colors.extend_from_slice(&mesh.colors);
+            merged.indices.extend(mesh.indices.iter().map(|i| i + offset));
+        }
+        // Normals, texture coordinates or tangents from only some of the meshes would no longer
+        // line up with the vertices
+        if meshes.iter().any(|m| m.normals.is_empty()) {
+            merged.normals.clear();
+        }
+        if meshes.iter().any(|m| m.texcoords.is_empty()) {
+            merged.texcoords.clear();
+        }
+        if meshes.iter().any(|m| m.tangents.is_empty()) {
+            merged.tangents.clear();
+        }
+        merged.index_count = merged.indices.len() as i32;
+        merged
     }
 }
 
-// Lunar terrain
+// Every object of an OBJ file, as a separate mesh
 
-pub struct Terrain;
-impl Terrain {
-    pub fn load(path: &str) -> Mesh {
-        println!("Loading terrain model...");
+pub struct Model {
+    pub meshes : Vec<Mesh>,
+}
+
+impl Model {
+    pub fn load(path: &str) -> Result<Model, String> {
+        println!("Loading model {}...", path);
         let before = std::time::Instant::now();
-        let (models, _materials)
+        let (models, materials)
             = tobj::load_obj(path,
                 &tobj::LoadOptions{
                     triangulate: true,
                     single_index: true,
                     ..Default::default()
                 }
-            ).expect("Failed to load terrain model");
+            ).map_err(|e| format!("Failed to load model {}: {}", path, e))?;
         let after = std::time::Instant::now();
         println!("Done in {:.3}ms.", after.duration_since(before).as_micros() as f32 / 1e3);
 
-        if models.len() > 1 || models.len() == 0 {
-            panic!("Please use a model with a single mesh!")
-            // You could try merging the vertices and indices
-            // of the separate meshes into a single mesh.
-            // I'll leave that as an optional exercise. ;)
+        if models.is_empty() {
+            return Err(format!("{} does not contain any objects", path));
         }
 
-        let terrain = models[0].to_owned();
-        println!("Loaded {} with {} points and {} triangles.",
-            terrain.name,
-            terrain.mesh.positions.len() /3,
-            terrain.mesh.indices.len() / 3,
-        );
+        // A missing or broken MTL file is not fatal, the meshes just get the default material
+        let materials = materials.unwrap_or_else(|e| {
+            println!("No materials loaded for {}: {}", path, e);
+            vec![]
+        });
 
-        Mesh::from(terrain.mesh, [1.0, 1.0, 1.0, 1.0])
+        let mut meshes = Vec::with_capacity(models.len());
+        for model in models {
+            println!("Loaded {} with {} points and {} triangles.",
+                model.name,
+                model.mesh.positions.len() / 3,
+                model.mesh.indices.len() / 3,
+            );
+            let mut material = model.mesh.material_id
+                .and_then(|id| materials.get(id))
+                .map(Material::from)
+                .unwrap_or_default();
+            // Texture paths in an MTL file are relative to the file itself
+            let directory = std::path::Path::new(path).parent().unwrap_or(std::path::Path::new(""));
+            for texture in material.diffuse_texture.iter_mut().chain(material.normal_texture.iter_mut()) {
+                *texture = directory.join(&texture).to_string_lossy().to_string();
+            }
+            let mut mesh = Mesh::from(model.mesh, [1.0, 1.0, 1.0, 1.0]);
+            mesh.name = model.name;
+            mesh.material = material;
+            mesh.validate().map_err(|e| format!("Malformed object {} in {}: {}", mesh.name, path, e))?;
+            if mesh.normals.is_empty() {
+                println!("{} has no normals, generating smooth ones.", mesh.name);
+                mesh.generate_normals(NormalMode::Smooth);
+            }
+            meshes.push(mesh);
+        }
+        Ok(Model { meshes })
     }
-}
 
+    pub fn get(&self, name: &str) -> Option<&Mesh> {
+        self.meshes.iter().find(|m| m.name == name)
+    }
 
-// Helicopter
+    // All objects of the model joined into a single mesh
+    pub fn merged(&self) -> Mesh {
+        match self.meshes.as_slice() {
+            [single] => single.clone(),
+            meshes => Mesh::merge("merged", meshes),
+        }
+    }
+}
 
-pub struct Helicopter {
-    pub body       : Mesh,
-    pub door       : Mesh,
-    pub main_rotor : Mesh,
-    pub tail_rotor : Mesh,
+// Lunar terrain
+
+#[allow(dead_code)]
+pub struct Terrain;
+#[allow(dead_code)]
+impl Terrain {
+    pub fn load(path: &str) -> Result<Mesh, String> {
+        // Terrain split into several objects is simply joined back together
+        Model::load(path).map(|model| model.merged())
+    }
 }
 
-// You can use square brackets to access the components of the helicopter, if you want to use loops!
-use std::ops::Index;
-impl Index<usize> for Helicopter {
-    type Output = Mesh;
-    fn index<'a>(&'a self, i: usize) -> &'a Mesh {
-        match i {
-            0 => &self.body,
-            1 => &self.main_rotor,
-            2 => &self.tail_rotor,
-            3 => &self.door,
-            _ => panic!("Invalid index, try [0,3]"),
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_obj(name: &str, source: &str) -> String {
+        let path = std::env::temp_dir().join(format!("gloom-rs-{}-{}.obj", name, std::process::id()));
+        std::fs::write(&path, source).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn loads_every_object_and_merges_with_rebased_indices() {
+        let path = write_obj("two_objects", "\
+o first
+v 0 0 0
+v 1 0 0
+v 0 1 0
+f 1 2 3
+o second
+v 0 0 1
+v 1 0 1
+v 0 1 1
+v 1 1 1
+f 4 5 6
+f 5 7 6
+");
+        let model = Model::load(&path).unwrap();
+        assert_eq!(model.meshes.len(), 2);
+        assert_eq!(model.get("second").unwrap().vertex_count(), 4);
+
+        // The file has no normals, so smooth ones are made
+        assert_eq!(model.get("first").unwrap().normals.len(), 3 * 3);
+
+        let merged = model.merged();
+        assert_eq!(merged.vertex_count(), 7);
+        assert_eq!(merged.colors.len(), 7 * 4);
+        assert_eq!(merged.index_count, 9);
+        assert!(merged.indices[3..].iter().all(|&i| (3..7).contains(&i)));
+        assert_eq!(&merged.vertices[9..12], &model.get("second").unwrap().vertices[0..3]);
+    }
+
+    #[test]
+    fn materials_are_read_from_the_mtl_file() {
+        let mtl = std::env::temp_dir().join(format!("gloom-rs-materials-{}.mtl", std::process::id()));
+        std::fs::write(&mtl, "newmtl shiny\nKd 0.8 0.1 0.1\nNs 96\nd 0.5\nmap_Kd rust.png\nmap_Bump rust_normal.png\n").unwrap();
+        let path = write_obj("materials", &format!("\
+mtllib {}
+o plain
+v 0 0 1
+v 1 0 1
+v 0 1 1
+f 1 2 3
+o painted
+usemtl shiny
+v 0 0 0
+v 1 0 0
+v 0 1 0
+f 4 5 6
+", mtl.file_name().unwrap().to_str().unwrap()));
+        let model = Model::load(&path).unwrap();
+
+        let painted = &model.get("painted").unwrap().material;
+        assert_eq!(painted.diffuse, [0.8, 0.1, 0.1]);
+        assert_eq!(painted.shininess, 96.0);
+        assert_eq!(painted.dissolve, 0.5);
+        let texture = std::env::temp_dir().join("rust.png");
+        assert_eq!(painted.diffuse_texture.as_deref(), texture.to_str());
+        let normal_map = std::env::temp_dir().join("rust_normal.png");
+        assert_eq!(painted.normal_texture.as_deref(), normal_map.to_str());
+        assert_eq!(painted.specular, Material::default().specular);
+
+        assert_eq!(model.get("plain").unwrap().material, Material::default());
+    }
+
+    // Two triangles meeting at a right angle along the edge from (0, 0, 0) to (0, 0, 1)
+    fn folded_quad() -> Mesh {
+        let mut mesh = Mesh::from(tobj::Mesh {
+            positions: vec![0.0, 0.0, 0.0,  0.0, 0.0, 1.0,  1.0, 0.0, 0.0,  0.0, 1.0, 0.0],
+            indices: vec![0, 1, 2,  0, 3, 1],
+            ..Default::default()
+        }, [1.0, 0.0, 0.0, 1.0]);
+        mesh.texcoords = vec![0.0, 0.0,  0.0, 1.0,  1.0, 0.0,  1.0, 1.0];
+        mesh
+    }
+
+    fn normal_of(mesh: &Mesh, vertex: u32) -> glm::Vec3 {
+        let i = vertex as usize * 3;
+        glm::vec3(mesh.normals[i], mesh.normals[i + 1], mesh.normals[i + 2])
+    }
+
+    #[test]
+    fn smooth_normals_are_shared_across_the_fold() {
+        let mut mesh = folded_quad();
+        mesh.generate_normals(NormalMode::Smooth);
+        assert_eq!(mesh.vertex_count(), 4);
+        assert!(mesh.validate().is_ok());
+
+        let diagonal = glm::vec3(1.0, 1.0, 0.0).normalize();
+        assert!((normal_of(&mesh, mesh.indices[0]) - diagonal).norm() < 1e-5);
+        assert!((normal_of(&mesh, mesh.indices[2]) - glm::vec3(0.0, 1.0, 0.0)).norm() < 1e-5);
+        assert!((normal_of(&mesh, mesh.indices[4]) - glm::vec3(1.0, 0.0, 0.0)).norm() < 1e-5);
+    }
+
+    #[test]
+    fn flat_normals_split_the_shared_edge() {
+        let mut mesh = folded_quad();
+        mesh.generate_normals(NormalMode::Flat);
+        assert_eq!(mesh.vertex_count(), 6);
+        assert_eq!(mesh.colors.len(), 6 * 4);
+        assert_eq!(mesh.texcoords.len(), 6 * 2);
+        assert!(mesh.validate().is_ok());
+
+        for &i in &mesh.indices[0..3] {
+            assert!((normal_of(&mesh, i) - glm::vec3(0.0, 1.0, 0.0)).norm() < 1e-5);
+        }
+        for &i in &mesh.indices[3..6] {
+            assert!((normal_of(&mesh, i) - glm::vec3(1.0, 0.0, 0.0)).norm() < 1e-5);
         }
     }
-}
 
-impl Helicopter {
-    pub fn load(path: &str) -> Self {
-        println!("Loading helicopter model...");
-        let before = std::time::Instant::now();
-        let (models, _materials)
-            = tobj::load_obj(path,
-                &tobj::LoadOptions{
-                    triangulate: true,
-                    single_index: true,
-                    ..Default::default()
-                }
-            ).expect("Failed to load helicopter model");
-        let after = std::time::Instant::now();
-        println!("Done in {:.3}ms!", after.duration_since(before).as_micros() as f32 / 1e3);
+    #[test]
+    fn crease_angle_decides_between_smooth_and_flat() {
+        let mut sharp = folded_quad();
+        sharp.generate_normals(NormalMode::Crease(45f32.to_radians()));
+        assert_eq!(sharp.vertex_count(), 6);
+
+        let mut soft = folded_quad();
+        soft.generate_normals(NormalMode::Crease(100f32.to_radians()));
+        assert_eq!(soft.vertex_count(), 4);
+    }
+
+    #[test]
+    fn validation_reports_mismatched_attributes() {
+        let mut mesh = folded_quad();
+        mesh.normals = vec![0.0; 6];
+        mesh.texcoords.pop();
+        let error = mesh.validate().unwrap_err();
+        assert!(error.contains("6 normal components for 4 vertices"), "{}", error);
+        assert!(error.contains("7 texture coordinate components"), "{}", error);
+    }
 
-        for model in &models {
-            println!("Loaded {} with {} points and {} triangles.", model.name, model.mesh.positions.len() / 3, model.mesh.indices.len() / 3);
+    // A unit quad in the xy plane facing +z, with u running along x unless mirrored
+    fn textured_quad(mirrored: bool) -> Mesh {
+        let mut mesh = Mesh::from(tobj::Mesh {
+            positions: vec![0.0, 0.0, 0.0,  1.0, 0.0, 0.0,  1.0, 1.0, 0.0,  0.0, 1.0, 0.0],
+            normals: [0.0, 0.0, 1.0].repeat(4),
+            texcoords: vec![0.0, 0.0,  1.0, 0.0,  1.0, 1.0,  0.0, 1.0],
+            indices: vec![0, 1, 2,  0, 2, 3],
+            ..Default::default()
+        }, [1.0; 4]);
+        if mirrored {
+            for u in mesh.texcoords.iter_mut().step_by(2) {
+                *u = 1.0 - *u;
+            }
         }
+        mesh
+    }
 
-        let body_model = models.iter().find(|m| m.name == "Body_body").expect("Incorrect model file!").to_owned();
-        let door_model = models.iter().find(|m| m.name == "Door_door").expect("Incorrect model file!").to_owned();
-        let main_rotor_model = models.iter().find(|m| m.name == "Main_Rotor_main_rotor").expect("Incorrect model file!").to_owned();
-        let tail_rotor_model = models.iter().find(|m| m.name == "Tail_Rotor_tail_rotor").expect("Incorrect model file!").to_owned();
+    #[test]
+    fn tangents_follow_the_texture_and_record_handedness() {
+        let mut mesh = textured_quad(false);
+        mesh.generate_tangents().unwrap();
+        assert!(mesh.validate().is_ok());
+        for tangent in mesh.tangents.chunks_exact(4) {
+            assert!((glm::vec3(tangent[0], tangent[1], tangent[2]) - glm::vec3(1.0, 0.0, 0.0)).norm() < 1e-5);
+            assert_eq!(tangent[3], 1.0);
+        }
 
-        Helicopter {
-            body:       Mesh::from(body_model.mesh,         [0.3, 0.3, 0.3, 1.0]),
-            door:       Mesh::from(door_model.mesh,         [0.1, 0.1, 0.3, 1.0]),
-            main_rotor: Mesh::from(main_rotor_model.mesh,   [0.3, 0.1, 0.1, 1.0]),
-            tail_rotor: Mesh::from(tail_rotor_model.mesh,   [0.1, 0.3, 0.1, 1.0]),
+        let mut mirrored = textured_quad(true);
+        mirrored.generate_tangents().unwrap();
+        for tangent in mirrored.tangents.chunks_exact(4) {
+            assert!((glm::vec3(tangent[0], tangent[1], tangent[2]) - glm::vec3(-1.0, 0.0, 0.0)).norm() < 1e-5);
+            assert_eq!(tangent[3], -1.0);
         }
     }
+
+    #[test]
+    fn tangents_need_texture_coordinates() {
+        let mut mesh = textured_quad(false);
+        mesh.texcoords.clear();
+        assert!(mesh.generate_tangents().is_err());
+
+        // And are dropped when the normals they were made against change
+        let mut mesh = textured_quad(false);
+        mesh.generate_tangents().unwrap();
+        mesh.generate_normals(NormalMode::Flat);
+        assert!(mesh.tangents.is_empty());
+    }
+
+    #[test]
+    fn missing_file_is_an_error() {
+        assert!(Model::load("./resources/does_not_exist.obj").is_err());
+    }
 }
@@ -0,0 +1,332 @@
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::shader::ShaderError;
+
+// GLSL has no way of sharing code between files, so shader sources are run through this before
+// they are compiled. It understands two things:
+//
+//  - `#include "lighting.glsl"` on a line of its own pastes in another file, found relative to
+//    the file including it. Every file becomes a separate source string number in the `#line`
+//    directives, so the driver's logs point at the right file and line. A file is only pasted in
+//    the first time it is included, and includes in comments or in `#if 0` blocks are left alone.
+//  - Defines given to the ShaderBuilder are inserted right after `#version`, which has to stay
+//    the first line of the shader.
+
+pub struct SourceFile {
+    pub path     : Option<String>,      // None for source passed in directly
+    pub source   : String,
+    pub modified : Option<SystemTime>,  // When the file was last changed, as of reading it
+}
+
+pub struct PreprocessedSource {
+    pub source : String,
+    pub files  : Vec<SourceFile>,   // Indexed by source string number
+}
+
+pub fn modification_time(path: &str) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+fn read_file(path: &str) -> Result<SourceFile, ShaderError> {
+    // Noted before reading, so that a change made while reading is not missed
+    let modified = modification_time(path);
+    let source = std::fs::read_to_string(path)
+        .map_err(|error| ShaderError::Io { path: path.to_string(), error })?;
+    Ok(SourceFile { path: Some(path.to_string()), source, modified })
+}
+
+pub fn preprocess_file(path: &str, defines: &[(String, String)]) -> Result<PreprocessedSource, ShaderError> {
+    preprocess_root(read_file(path)?, defines)
+}
+
+// Includes in source passed in directly are found relative to the working directory
+pub fn preprocess(source: &str, defines: &[(String, String)]) -> Result<PreprocessedSource, ShaderError> {
+    preprocess_root(SourceFile { path: None, source: source.to_string(), modified: None }, defines)
+}
+
+fn preprocess_root(root: SourceFile, defines: &[(String, String)]) -> Result<PreprocessedSource, ShaderError> {
+    let mut preprocessor = Preprocessor { files: vec![], including: vec![], included: vec![], output: String::new() };
+    if let Some(path) = &root.path {
+        let path = canonical(path)?;
+        preprocessor.including.push(path.clone());
+        preprocessor.included.push(path);
+    }
+    let has_version = root.source.lines().any(|line| line.trim_start().starts_with("#version"));
+    preprocessor.files.push(root);
+
+    if !has_version {
+        preprocessor.write_defines(defines, 1);
+    }
+    preprocessor.process(0, defines)?;
+    Ok(PreprocessedSource { source: preprocessor.output, files: preprocessor.files })
+}
+
+fn canonical(path: &str) -> Result<PathBuf, ShaderError> {
+    Path::new(path).canonicalize().map_err(|error| ShaderError::Io { path: path.to_string(), error })
+}
+
+struct Preprocessor {
+    files     : Vec<SourceFile>,
+    including : Vec<PathBuf>,   // The files currently being included, outermost first
+    included  : Vec<PathBuf>,   // Every file pasted in so far
+    output    : String,
+}
+
+// Whether the lines in an #if block are compiled, as far as can be told without evaluating it
+#[derive(Clone, Copy, PartialEq)]
+enum Condition {
+    Active,
+    Inactive,
+    Unknown,
+}
+
+// Whether a line ends inside a /* */ comment, given whether it starts in one
+fn ends_in_comment(line: &str, mut in_comment: bool) -> bool {
+    let mut rest = line;
+    loop {
+        if in_comment {
+            match rest.find("*/") {
+                Some(end) => { rest = &rest[end + 2..]; in_comment = false; }
+                None => return true,
+            }
+        } else {
+            match (rest.find("/*"), rest.find("//")) {
+                (Some(start), Some(comment)) if comment < start => return false,
+                (Some(start), _) => { rest = &rest[start + 2..]; in_comment = true; }
+                (None, _) => return false,
+            }
+        }
+    }
+}
+
+impl Preprocessor {
+    fn write_defines(&mut self, defines: &[(String, String)], next_line: usize) {
+        if defines.is_empty() {
+            return;
+        }
+        for (name, value) in defines {
+            self.output += &format!("#define {} {}\n", name, value);
+        }
+        self.output += &format!("#line {} 0\n", next_line);
+    }
+
+    fn process(&mut self, file: usize, defines: &[(String, String)]) -> Result<(), ShaderError> {
+        let source = self.files[file].source.clone();
+        let path = self.files[file].path.clone();
+        let directory = path.as_deref().and_then(|p| Path::new(p).parent()).unwrap_or(Path::new("")).to_path_buf();
+        let error = |line: usize, message: String| ShaderError::Include {
+            path: path.clone().unwrap_or_else(|| "source".to_string()),
+            line: line as u32,
+            message,
+        };
+
+        let mut in_comment = false;
+        let mut conditions: Vec<Condition> = vec![];
+        for (index, line) in source.lines().enumerate() {
+            let number = index + 1;
+            let directive = line.trim_start();
+            let commented = in_comment;
+            in_comment = ends_in_comment(line, in_comment);
+            if commented {
+                self.output += line;
+                self.output += "\n";
+                continue;
+            }
+
+            let keyword = directive.strip_prefix('#').map(|rest| rest.split_whitespace().collect::<Vec<_>>());
+            match keyword.as_deref() {
+                Some(["if", "0", ..]) => conditions.push(Condition::Inactive),
+                Some(["if", "1", ..]) => conditions.push(Condition::Active),
+                Some(["if", ..]) | Some(["ifdef", ..]) | Some(["ifndef", ..]) => conditions.push(Condition::Unknown),
+                Some(["elif", ..]) => if let Some(condition) = conditions.last_mut() {
+                    // After a block that was compiled none of the others are
+                    *condition = if *condition == Condition::Active { Condition::Inactive } else { Condition::Unknown };
+                },
+                Some(["else", ..]) => if let Some(condition) = conditions.last_mut() {
+                    *condition = match *condition {
+                        Condition::Active => Condition::Inactive,
+                        Condition::Inactive => Condition::Active,
+                        Condition::Unknown => Condition::Unknown,
+                    };
+                },
+                Some(["endif", ..]) => { conditions.pop(); }
+                _ => {}
+            }
+
+            if directive.starts_with("#version") {
+                if file != 0 {
+                    return Err(error(number, "only the main shader file may declare #version".to_string()));
+                }
+                self.output += line;
+                self.output += "\n";
+                self.write_defines(defines, number + 1);
+                continue;
+            }
+
+            let include = match directive.strip_prefix("#include") {
+                Some(rest) if !conditions.contains(&Condition::Inactive) => rest.trim(),
+                _ => {
+                    self.output += line;
+                    self.output += "\n";
+                    continue;
+                }
+            };
+            let name = include.strip_prefix('"').and_then(|rest| rest.strip_suffix('"'))
+                .ok_or_else(|| error(number, format!("expected #include \"file\", found #include {}", include)))?;
+
+            let include_path = directory.join(name).to_string_lossy().to_string();
+            let canonical_path = canonical(&include_path)
+                .map_err(|_| error(number, format!("cannot find {} to include", include_path)))?;
+            if let Some(start) = self.including.iter().position(|p| *p == canonical_path) {
+                let cycle: Vec<String> = self.including[start..].iter()
+                    .chain(std::iter::once(&canonical_path))
+                    .map(|p| p.file_name().unwrap_or_default().to_string_lossy().to_string())
+                    .collect();
+                return Err(error(number, format!("{} is included in a cycle: {}", name, cycle.join(" -> "))));
+            }
+            if self.included.contains(&canonical_path) {
+                // Already pasted in, so that shared files can be included from several places
+                self.output += "\n";
+                continue;
+            }
+
+            let included = self.files.len();
+            let included_file = read_file(&include_path)
+                .map_err(|e| error(number, e.to_string()))?;
+            self.files.push(included_file);
+            self.included.push(canonical_path.clone());
+            self.including.push(canonical_path);
+            self.output += &format!("#line 1 {}\n", included);
+            self.process(included, defines)?;
+            self.including.pop();
+            self.output += &format!("#line {} {}\n", number + 1, file);
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_files(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("gloom-rs-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        for (file, source) in files {
+            let path = directory.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, source).unwrap();
+        }
+        directory
+    }
+
+    #[test]
+    fn includes_are_pasted_in_with_line_directives() {
+        let directory = write_files("include", &[
+            ("main.frag", "#version 430 core\n#include \"lib/light.glsl\"\nvoid main() {}\n"),
+            ("lib/light.glsl", "#include \"common.glsl\"\nvec3 light;\n"),
+            ("lib/common.glsl", "float pi;\n"),
+        ]);
+
+        let main = directory.join("main.frag");
+        let preprocessed = preprocess_file(main.to_str().unwrap(), &[("SHADOWS".to_string(), "1".to_string())]).unwrap();
+        assert_eq!(preprocessed.source, "\
+#version 430 core
+#define SHADOWS 1
+#line 2 0
+#line 1 1
+#line 1 2
+float pi;
+#line 2 1
+vec3 light;
+#line 3 0
+void main() {}
+");
+        assert_eq!(preprocessed.files.len(), 3);
+        assert!(preprocessed.files[2].path.as_deref().unwrap().ends_with("common.glsl"));
+    }
+
+    #[test]
+    fn cycles_are_reported() {
+        let directory = write_files("cycle", &[
+            ("main.frag", "#include \"a.glsl\"\n"),
+            ("a.glsl", "#include \"b.glsl\"\n"),
+            ("b.glsl", "\n#include \"a.glsl\"\n"),
+        ]);
+        let main = directory.join("main.frag");
+        match preprocess_file(main.to_str().unwrap(), &[]) {
+            Err(ShaderError::Include { path, line, message }) => {
+                assert!(path.ends_with("b.glsl"), "{}", path);
+                assert_eq!(line, 2);
+                assert_eq!(message, "a.glsl is included in a cycle: a.glsl -> b.glsl -> a.glsl");
+            }
+            _ => panic!("The cycle was not found"),
+        }
+    }
+
+    #[test]
+    fn files_are_only_included_once() {
+        let directory = write_files("diamond", &[
+            ("main.frag", "#include \"a.glsl\"\n#include \"b.glsl\"\n"),
+            ("a.glsl", "#include \"common.glsl\"\n"),
+            ("b.glsl", "#include \"common.glsl\"\nvec3 b;\n"),
+            ("common.glsl", "float pi;\n"),
+        ]);
+        let main = directory.join("main.frag");
+        let preprocessed = preprocess_file(main.to_str().unwrap(), &[]).unwrap();
+        assert_eq!(preprocessed.source.matches("float pi;").count(), 1);
+        assert_eq!(preprocessed.files.len(), 4);
+        assert!(preprocessed.source.ends_with("#line 1 3\n\nvec3 b;\n#line 3 0\n"), "{}", preprocessed.source);
+    }
+
+    #[test]
+    fn includes_in_comments_and_disabled_blocks_are_left_alone() {
+        let source = "\
+// #include \"missing.glsl\"
+/* old lighting:
+#include \"missing.glsl\"
+*/
+#if 0
+#include \"missing.glsl\"
+#elif defined(SHADOWS)
+#endif
+void main() {}
+";
+        let preprocessed = preprocess(source, &[]).unwrap();
+        assert_eq!(preprocessed.source, source);
+        assert_eq!(preprocessed.files.len(), 1);
+    }
+
+    #[test]
+    fn missing_includes_point_at_the_include() {
+        let directory = write_files("missing", &[
+            ("main.frag", "#version 430 core\n\n#include \"missing.glsl\"\n"),
+        ]);
+        let main = directory.join("main.frag");
+        match preprocess_file(main.to_str().unwrap(), &[]) {
+            Err(ShaderError::Include { path, line, message }) => {
+                assert!(path.ends_with("main.frag"), "{}", path);
+                assert_eq!(line, 3);
+                assert!(message.starts_with("cannot find ") && message.ends_with("missing.glsl to include"), "{}", message);
+            }
+            _ => panic!("The missing include was not reported"),
+        }
+    }
+
+    #[test]
+    fn comments_are_followed_across_lines() {
+        assert!(ends_in_comment("vec3 a; /* start", false));
+        assert!(ends_in_comment("still inside", true));
+        assert!(!ends_in_comment("end */ vec3 b;", true));
+        assert!(!ends_in_comment("// not /* a block", false));
+        assert!(!ends_in_comment("/* one */ /* two */", false));
+    }
+
+    #[test]
+    fn defines_go_first_without_a_version() {
+        let preprocessed = preprocess("void main() {}\n", &[("DEBUG".to_string(), String::new())]).unwrap();
+        assert_eq!(preprocessed.source, "#define DEBUG \n#line 1 0\nvoid main() {}\n");
+    }
+}
@@ -0,0 +1,200 @@
+use crate::shader::{c_string, ShaderError};
+use crate::vertex::{ComponentType, VertexLayout};
+
+// What a linked program takes as input, as reported by the driver. Only what the shaders actually
+// use is active, anything optimized away is left out.
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ActiveAttribute {
+    pub name     : String,
+    pub gl_type  : gl::types::GLenum,
+    pub size     : i32,   // Number of elements, for arrays
+    pub location : i32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ActiveUniform {
+    pub name     : String,
+    pub gl_type  : gl::types::GLenum,
+    pub size     : i32,
+    pub location : i32,             // -1 for members of uniform blocks
+    pub block    : Option<String>,  // The uniform block it is a member of
+}
+
+// The GLSL name of a type, how many scalars it is made of, and whether those are integers
+pub fn type_info(gl_type: gl::types::GLenum) -> (&'static str, usize, bool) {
+    match gl_type {
+        gl::FLOAT                   => ("float", 1, false),
+        gl::FLOAT_VEC2              => ("vec2", 2, false),
+        gl::FLOAT_VEC3              => ("vec3", 3, false),
+        gl::FLOAT_VEC4              => ("vec4", 4, false),
+        gl::INT                     => ("int", 1, true),
+        gl::INT_VEC2                => ("ivec2", 2, true),
+        gl::INT_VEC3                => ("ivec3", 3, true),
+        gl::INT_VEC4                => ("ivec4", 4, true),
+        gl::UNSIGNED_INT            => ("uint", 1, true),
+        gl::UNSIGNED_INT_VEC2       => ("uvec2", 2, true),
+        gl::UNSIGNED_INT_VEC3       => ("uvec3", 3, true),
+        gl::UNSIGNED_INT_VEC4       => ("uvec4", 4, true),
+        gl::BOOL                    => ("bool", 1, true),
+        gl::FLOAT_MAT2              => ("mat2", 4, false),
+        gl::FLOAT_MAT3              => ("mat3", 9, false),
+        gl::FLOAT_MAT4              => ("mat4", 16, false),
+        gl::SAMPLER_2D              => ("sampler2D", 1, true),
+        gl::SAMPLER_3D              => ("sampler3D", 1, true),
+        gl::SAMPLER_CUBE            => ("samplerCube", 1, true),
+        gl::SAMPLER_2D_SHADOW       => ("sampler2DShadow", 1, true),
+        _                           => ("unknown", 0, false),
+    }
+}
+
+// Reads a name written by one of the glGetActive* functions into a buffer of `max_length` bytes
+unsafe fn read_name(max_length: i32, read: impl FnOnce(i32, &mut i32, *mut gl::types::GLchar)) -> String {
+    let mut name = vec![0u8; max_length.max(1) as usize];
+    let mut length = 0;
+    read(name.len() as i32, &mut length, name.as_mut_ptr() as *mut gl::types::GLchar);
+    name.truncate(length.max(0) as usize);
+    String::from_utf8_lossy(&name).to_string()
+}
+
+pub unsafe fn active_attributes(program: u32) -> Result<Vec<ActiveAttribute>, ShaderError> {
+    let mut count = 0;
+    let mut max_length = 0;
+    gl::GetProgramiv(program, gl::ACTIVE_ATTRIBUTES, &mut count);
+    gl::GetProgramiv(program, gl::ACTIVE_ATTRIBUTE_MAX_LENGTH, &mut max_length);
+
+    let mut attributes = Vec::with_capacity(count.max(0) as usize);
+    for index in 0..count.max(0) as u32 {
+        let (mut size, mut gl_type) = (0, 0);
+        let name = read_name(max_length, |capacity, length, buffer| {
+            gl::GetActiveAttrib(program, index, capacity, length, &mut size, &mut gl_type, buffer)
+        });
+        let name_cstr = c_string(&name, || format!("Attribute name {:?}", name))?;
+        let location = gl::GetAttribLocation(program, name_cstr.as_ptr());
+        attributes.push(ActiveAttribute { name, gl_type, size, location });
+    }
+    // Built-ins such as gl_VertexID are listed too on some drivers, but are not fed by a VAO
+    attributes.retain(|a| !a.name.starts_with("gl_"));
+    attributes.sort_by_key(|a| a.location);
+    Ok(attributes)
+}
+
+pub unsafe fn active_uniforms(program: u32) -> Result<Vec<ActiveUniform>, ShaderError> {
+    let mut count = 0;
+    let mut max_length = 0;
+    let mut max_block_length = 0;
+    gl::GetProgramiv(program, gl::ACTIVE_UNIFORMS, &mut count);
+    gl::GetProgramiv(program, gl::ACTIVE_UNIFORM_MAX_LENGTH, &mut max_length);
+    gl::GetProgramiv(program, gl::ACTIVE_UNIFORM_BLOCK_MAX_NAME_LENGTH, &mut max_block_length);
+
+    let mut uniforms = Vec::with_capacity(count.max(0) as usize);
+    for index in 0..count.max(0) as u32 {
+        let (mut size, mut gl_type) = (0, 0);
+        let name = read_name(max_length, |capacity, length, buffer| {
+            gl::GetActiveUniform(program, index, capacity, length, &mut size, &mut gl_type, buffer)
+        });
+        let name_cstr = c_string(&name, || format!("Uniform name {:?}", name))?;
+        let location = gl::GetUniformLocation(program, name_cstr.as_ptr());
+
+        let mut block_index = -1;
+        gl::GetActiveUniformsiv(program, 1, &index, gl::UNIFORM_BLOCK_INDEX, &mut block_index);
+        let block = if block_index >= 0 {
+            Some(read_name(max_block_length, |capacity, length, buffer| {
+                gl::GetActiveUniformBlockName(program, block_index as u32, capacity, length, buffer)
+            }))
+        } else {
+            None
+        };
+        uniforms.push(ActiveUniform { name, gl_type, size, location, block });
+    }
+    uniforms.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(uniforms)
+}
+
+// Compares the attributes a vertex shader reads with what a VAO feeds it. Attributes the layout
+// leaves out are reported too, as the shader then reads a constant (0, 0, 0, 1) from them, unless
+// they are `optional` because the shader only uses them when told to by a uniform.
+pub fn check_vertex_layout(attributes: &[ActiveAttribute], layout: &VertexLayout, optional: &[&str]) -> Vec<String> {
+    let mut problems = vec![];
+    for attribute in attributes {
+        let (type_name, components, is_integer) = type_info(attribute.gl_type);
+        let provided = match layout.get(&attribute.name) {
+            Some(provided) => provided,
+            None => {
+                match layout.attributes().iter().find(|a| a.location as i32 == attribute.location) {
+                    Some(other) => problems.push(format!(
+                        "the shader reads {} {} from location {}, but the VAO puts {} there",
+                        type_name, attribute.name, attribute.location, other.name)),
+                    None if optional.contains(&attribute.name.as_str()) => {},
+                    None => problems.push(format!(
+                        "the shader reads {} {} from location {}, which the VAO does not provide",
+                        type_name, attribute.name, attribute.location)),
+                }
+                continue;
+            }
+        };
+        if provided.location as i32 != attribute.location {
+            problems.push(format!("the shader reads {} from location {}, but the VAO puts it at {}",
+                attribute.name, attribute.location, provided.location));
+        }
+        let provides_integers = provided.component_type != ComponentType::Float && !provided.normalized;
+        if provides_integers != is_integer {
+            problems.push(format!("the shader reads {} as {}, but the VAO provides {}",
+                attribute.name, type_name, if provides_integers { "integers" } else { "floats" }));
+        }
+        if components > 0 && provided.components > components {
+            problems.push(format!("the VAO provides {} components of {}, but the shader only reads {} as {}",
+                provided.components, attribute.name, components, type_name));
+        }
+    }
+    problems
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attribute(name: &str, gl_type: gl::types::GLenum, location: i32) -> ActiveAttribute {
+        ActiveAttribute { name: name.to_string(), gl_type, size: 1, location }
+    }
+
+    #[test]
+    fn matching_layouts_have_no_problems() {
+        let attributes = [attribute("position", gl::FLOAT_VEC3, 0), attribute("color", gl::FLOAT_VEC4, 1)];
+        let layout = VertexLayout::new()
+            .with("position", 0, 3, ComponentType::Float, false)
+            .with("color", 1, 4, ComponentType::UnsignedByte, true);
+        assert!(check_vertex_layout(&attributes, &layout, &[]).is_empty());
+    }
+
+    #[test]
+    fn mismatched_layouts_are_explained() {
+        let attributes = [
+            attribute("position", gl::FLOAT_VEC3, 0),
+            attribute("normal", gl::FLOAT_VEC3, 2),
+            attribute("bones", gl::INT_VEC4, 5),
+            attribute("texcoord", gl::FLOAT_VEC2, 3),
+        ];
+        let layout = VertexLayout::new()
+            .with("position", 0, 4, ComponentType::Float, false)
+            .with("color", 2, 4, ComponentType::Float, false)
+            .with("bones", 5, 4, ComponentType::Float, false)
+            .with("texcoord", 4, 2, ComponentType::Float, false);
+        assert_eq!(check_vertex_layout(&attributes, &layout, &["normal"]), [
+            "the VAO provides 4 components of position, but the shader only reads 3 as vec3",
+            "the shader reads vec3 normal from location 2, but the VAO puts color there",
+            "the shader reads bones as ivec4, but the VAO provides floats",
+            "the shader reads texcoord from location 3, but the VAO puts it at 4",
+        ]);
+    }
+
+    #[test]
+    fn optional_attributes_may_be_left_out() {
+        let attributes = [attribute("position", gl::FLOAT_VEC3, 0), attribute("tangent", gl::FLOAT_VEC4, 4)];
+        let layout = VertexLayout::new().with("position", 0, 3, ComponentType::Float, false);
+        assert!(check_vertex_layout(&attributes, &layout, &["tangent"]).is_empty());
+        assert_eq!(check_vertex_layout(&attributes, &layout, &[]),
+            ["the shader reads vec4 tangent from location 4, which the VAO does not provide"]);
+    }
+}
@@ -0,0 +1,528 @@
+extern crate nalgebra_glm as glm;
+
+use std::collections::HashMap;
+use serde::{Serialize, Deserialize};
+
+use crate::mesh::{Material, Mesh, Model, NormalMode};
+use crate::articulated_model::ArticulatedModel;
+use crate::gpu::{Texture, VertexArray};
+use crate::scene_graph::{SceneGraph, SceneNode, NodeId};
+use crate::toolbox::simple_heading_animation;
+use crate::vertex::VertexLayout;
+
+// A scene file is a RON document listing the meshes to load, and the tree of nodes below the
+// root of the scene graph which draw them. Everything but a mesh's name and path is optional:
+//
+//     #![enable(implicit_some)]
+//     (
+//         meshes: [
+//             (name: "terrain", path: "./resources/lunarsurface.obj", texture: "./resources/moon.png"),
+//             (name: "body", path: "./resources/helicopter.obj", object: "Body_body", color: (0.3, 0.3, 0.3, 1.0)),
+//         ],
+//         models: [
+//             (name: "helicopter", manifest: "./resources/helicopter.ron"),
+//         ],
+//         nodes: [
+//             (name: "terrain", mesh: "terrain", children: [
+//                 (name: "statue", mesh: "body", position: (0.0, 20.0, 0.0)),
+//                 (name: "helicopter_0", model: "helicopter", animation: Heading(time_offset: 0.0, altitude: 5.0)),
+//             ]),
+//         ],
+//     )
+//
+// A node using an articulated model (see articulated_model.rs) gets a child node for each of
+// its parts, named after the part's role. These parts are not written back out when saving.
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SceneDescription {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub meshes : Vec<MeshDescription>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub models : Vec<ModelDescription>,
+    pub nodes  : Vec<NodeDescription>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MeshDescription {
+    pub name : String,
+    pub path : String,
+    // Which object of the OBJ file to use, if left out all of them are merged into one mesh
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub object : Option<String>,
+    #[serde(default = "white", skip_serializing_if = "is_white")]
+    pub color : [f32; 4],
+    // An image to paint the mesh with, in place of any diffuse texture given by its material
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub texture : Option<String>,
+    // A tangent space normal map, in place of any given by its material. Meshes with a normal map
+    // need texture coordinates, as tangents are generated from them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub normal_map : Option<String>,
+    // Replaces the normals of the OBJ file with generated ones, e.g. `normals: Crease(0.5)`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub normals : Option<NormalMode>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ModelDescription {
+    pub name     : String,
+    pub manifest : String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct NodeDescription {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name : Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mesh : Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model : Option<String>,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub position : [f32; 3],
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub rotation : [f32; 3],
+    #[serde(default = "one", skip_serializing_if = "is_one")]
+    pub scale : [f32; 3],
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub reference_point : [f32; 3],
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub animation : Option<Animation>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub children : Vec<NodeDescription>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum Animation {
+    // Keeps turning around the X, Y and Z axes, measured in radians per second
+    Spin { rates: [f32; 3] },
+    // Follows the path of toolbox::simple_heading_animation, at a fixed altitude
+    Heading { time_offset: f32, altitude: f32 },
+}
+
+pub(crate) fn white() -> [f32; 4] { [1.0, 1.0, 1.0, 1.0] }
+pub(crate) fn one() -> [f32; 3] { [1.0, 1.0, 1.0] }
+pub(crate) fn is_white(v: &[f32; 4]) -> bool { *v == white() }
+pub(crate) fn is_one(v: &[f32; 3]) -> bool { *v == one() }
+pub(crate) fn is_zero(v: &[f32; 3]) -> bool { *v == [0.0, 0.0, 0.0] }
+
+impl Animation {
+    pub fn apply(&self, node: &mut SceneNode, elapsed: f32, delta_time: f32) {
+        match self {
+            Animation::Spin { rates } => {
+                node.rotation += glm::Vec3::from(*rates) * delta_time;
+            }
+            Animation::Heading { time_offset, altitude } => {
+                let heading = simple_heading_animation(elapsed + time_offset);
+                node.position = glm::vec3(heading.x, *altitude, heading.z);
+                node.rotation = glm::vec3(heading.pitch, heading.yaw, heading.roll);
+            }
+        }
+    }
+}
+
+
+struct LoadedMesh {
+    description : MeshDescription,
+    vao         : VertexArray,   // Owned here, the nodes drawing it only know its id
+    index_count : i32,
+    layout      : VertexLayout,  // What the VAO feeds the shaders
+    material    : Material,
+    texture_id  : u32,
+    normal_map_id : u32,
+}
+
+struct LoadedPart {
+    role        : String,
+    parent      : Option<usize>,
+    vao         : VertexArray,
+    index_count : i32,
+    layout      : VertexLayout,
+    material    : Material,
+    texture_id  : u32,
+    normal_map_id : u32,
+    pivot       : glm::Vec3,
+    animation   : Option<Animation>,
+}
+
+struct LoadedModel {
+    description : ModelDescription,
+    parts       : Vec<LoadedPart>,
+}
+
+// A scene graph built from a scene file, along with what is needed to animate it and to write
+// it back out again
+pub struct Scene {
+    pub graph       : SceneGraph,
+    pub animations  : Vec<(NodeId, Animation)>,
+    meshes          : Vec<LoadedMesh>,
+    models          : Vec<LoadedModel>,
+    #[allow(dead_code)]
+    textures        : Vec<Texture>,           // Owned here, the nodes painted with them only know their ids
+    model_instances : Vec<(NodeId, String)>,  // Nodes using an articulated model, and its name
+    model_parts     : Vec<NodeId>,            // Nodes created for the parts of those models
+}
+
+impl Scene {
+    // Reads a scene file and loads every mesh and model it lists, handing each mesh to
+    // `create_vao` and the path of each texture they use to `load_texture`
+    pub fn load<F, T>(path: &str, create_vao: F, load_texture: T) -> Result<Scene, String>
+    where
+        F: FnMut(&Mesh) -> Result<VertexArray, String>,
+        T: FnMut(&str) -> Result<Texture, String>,
+    {
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read scene file {}: {}", path, e))?;
+        let description: SceneDescription = ron::from_str(&source)
+            .map_err(|e| format!("Failed to parse scene file {}: {}", path, e))?;
+        Scene::from_description(description, create_vao, load_texture)
+    }
+
+    pub fn from_description<F, T>(description: SceneDescription, mut create_vao: F, mut load_texture: T) -> Result<Scene, String>
+    where
+        F: FnMut(&Mesh) -> Result<VertexArray, String>,
+        T: FnMut(&str) -> Result<Texture, String>,
+    {
+        // Every texture is only loaded once, no matter how many meshes use it
+        let mut textures: Vec<Texture> = vec![];
+        let mut texture_ids: HashMap<String, u32> = HashMap::new();
+        let mut texture_id = |path: Option<&String>| -> Result<u32, String> {
+            match path {
+                Some(path) if texture_ids.contains_key(path) => Ok(texture_ids[path]),
+                Some(path) => {
+                    let texture = load_texture(path)?;
+                    let id = texture.id();
+                    texture_ids.insert(path.clone(), id);
+                    textures.push(texture);
+                    Ok(id)
+                }
+                None => Ok(0),
+            }
+        };
+
+        // Every OBJ file is only read once, no matter how many of its objects are used
+        let mut models: HashMap<String, Model> = HashMap::new();
+        let mut meshes = Vec::with_capacity(description.meshes.len());
+        for mesh_description in description.meshes {
+            if !models.contains_key(&mesh_description.path) {
+                models.insert(mesh_description.path.clone(), Model::load(&mesh_description.path)?);
+            }
+            let model = &models[&mesh_description.path];
+
+            // Without an object name, every object of the file is drawn as one
+            let mut mesh = match &mesh_description.object {
+                Some(object) => {
+                    model.get(object)
+                        .ok_or_else(|| format!("{} has no object named {}", mesh_description.path, object))?
+                        .clone()
+                }
+                None => model.merged(),
+            };
+            if let Some(mode) = mesh_description.normals {
+                mesh.generate_normals(mode);
+            }
+            mesh.set_color(mesh_description.color);
+
+            // Textures given in the scene file replace those of the material
+            if mesh_description.texture.is_some() {
+                mesh.material.diffuse_texture = mesh_description.texture.clone();
+            }
+            if mesh_description.normal_map.is_some() {
+                mesh.material.normal_texture = mesh_description.normal_map.clone();
+            }
+            if mesh.material.normal_texture.is_some() {
+                mesh.generate_tangents()
+                    .map_err(|e| format!("Cannot normal map mesh {}: {}", mesh_description.name, e))?;
+            }
+            meshes.push(LoadedMesh {
+                vao         : create_vao(&mesh)?,
+                index_count : mesh.index_count,
+                layout      : mesh.vertex_layout(),
+                texture_id  : texture_id(mesh.material.diffuse_texture.as_ref())?,
+                normal_map_id : texture_id(mesh.material.normal_texture.as_ref())?,
+                material    : mesh.material,
+                description : mesh_description,
+            });
+        }
+
+        let mut loaded_models = Vec::with_capacity(description.models.len());
+        for model_description in description.models {
+            let model = ArticulatedModel::load(&model_description.manifest)?;
+            let mut parts = Vec::with_capacity(model.parts.len());
+            for mut part in model.parts {
+                if part.mesh.material.normal_texture.is_some() {
+                    part.mesh.generate_tangents()
+                        .map_err(|e| format!("Cannot normal map the {} of {}: {}", part.role, model_description.name, e))?;
+                }
+                parts.push(LoadedPart {
+                    vao         : create_vao(&part.mesh)?,
+                    index_count : part.mesh.index_count,
+                    layout      : part.mesh.vertex_layout(),
+                    texture_id  : texture_id(part.mesh.material.diffuse_texture.as_ref())?,
+                    normal_map_id : texture_id(part.mesh.material.normal_texture.as_ref())?,
+                    material    : part.mesh.material,
+                    role        : part.role,
+                    parent      : part.parent,
+                    pivot       : part.pivot,
+                    animation   : part.animation,
+                });
+            }
+            loaded_models.push(LoadedModel { description: model_description, parts });
+        }
+
+        let mut scene = Scene {
+            graph           : SceneGraph::new(),
+            animations      : vec![],
+            meshes,
+            models          : loaded_models,
+            textures,
+            model_instances : vec![],
+            model_parts     : vec![],
+        };
+        let root = scene.graph.root();
+        for node in &description.nodes {
+            scene.add_node(root, node)?;
+        }
+        Ok(scene)
+    }
+
+    fn add_node(&mut self, parent: NodeId, description: &NodeDescription) -> Result<(), String> {
+        let mut node = match &description.mesh {
+            Some(name) => {
+                let mesh = self.meshes.iter().find(|m| &m.description.name == name)
+                    .ok_or_else(|| format!("Node refers to unknown mesh {}", name))?;
+                let mut node = SceneNode::from_vao(mesh.vao.id(), mesh.index_count);
+                node.material = mesh.material.clone();
+                node.texture_id = mesh.texture_id;
+                node.normal_map_id = mesh.normal_map_id;
+                node
+            }
+            None => SceneNode::new(),
+        };
+        node.name            = description.name.clone();
+        node.position        = description.position.into();
+        node.rotation        = description.rotation.into();
+        node.scale           = description.scale.into();
+        node.reference_point = description.reference_point.into();
+
+        let id = self.graph.add_child(parent, node);
+        if let Some(animation) = &description.animation {
+            self.animations.push((id, animation.clone()));
+        }
+        if let Some(name) = &description.model {
+            self.add_model_parts(id, name)?;
+        }
+        for child in &description.children {
+            self.add_node(id, child)?;
+        }
+        Ok(())
+    }
+
+    fn add_model_parts(&mut self, instance: NodeId, model_name: &str) -> Result<(), String> {
+        let model = self.models.iter().find(|m| m.description.name == model_name)
+            .ok_or_else(|| format!("Node refers to unknown model {}", model_name))?;
+
+        let mut part_ids: Vec<NodeId> = Vec::with_capacity(model.parts.len());
+        for part in &model.parts {
+            let mut node = SceneNode::from_vao(part.vao.id(), part.index_count);
+            node.name = Some(part.role.clone());
+            node.material = part.material.clone();
+            node.texture_id = part.texture_id;
+            node.normal_map_id = part.normal_map_id;
+            node.reference_point = part.pivot;
+
+            let parent = part.parent.map_or(instance, |index| part_ids[index]);
+            let id = self.graph.add_child(parent, node);
+            if let Some(animation) = &part.animation {
+                self.animations.push((id, animation.clone()));
+            }
+            part_ids.push(id);
+        }
+        self.model_instances.push((instance, model_name.to_string()));
+        self.model_parts.extend(part_ids);
+        Ok(())
+    }
+
+    // The name, material and vertex layout of every VAO in the scene, for checking them against
+    // the shaders again after these change
+    pub fn vertex_layouts(&self) -> Vec<(String, &Material, &VertexLayout)> {
+        let meshes = self.meshes.iter()
+            .map(|m| (m.description.name.clone(), &m.material, &m.layout));
+        let parts = self.models.iter().flat_map(|model| model.parts.iter()
+            .map(move |p| (format!("{}/{}", model.description.name, p.role), &p.material, &p.layout)));
+        meshes.chain(parts).collect()
+    }
+
+    // Removes a node and its subtree from the graph, along with their animations and what they
+    // were instantiated from
+    #[allow(dead_code)]
+    pub fn remove(&mut self, id: NodeId) -> Option<SceneNode> {
+        let node = self.graph.remove(id)?;
+        let graph = &self.graph;
+        self.animations.retain(|(a, _)| graph.contains(*a));
+        self.model_instances.retain(|(i, _)| graph.contains(*i));
+        self.model_parts.retain(|&p| graph.contains(p));
+        Some(node)
+    }
+
+    // Advances every animation in the scene. Nodes removed straight from the graph are skipped.
+    pub fn animate(&mut self, elapsed: f32, delta_time: f32) {
+        for (id, animation) in &self.animations {
+            if let Some(node) = self.graph.get_mut(*id) {
+                animation.apply(node, elapsed, delta_time);
+            }
+        }
+    }
+
+    // Describes the scene graph as it currently is
+    pub fn describe(&self) -> Result<SceneDescription, String> {
+        let root = &self.graph[self.graph.root()];
+        Ok(SceneDescription {
+            meshes : self.meshes.iter().map(|m| m.description.clone()).collect(),
+            models : self.models.iter().map(|m| m.description.clone()).collect(),
+            nodes  : self.describe_children(root.children())?,
+        })
+    }
+
+    fn describe_children(&self, children: &[NodeId]) -> Result<Vec<NodeDescription>, String> {
+        children.iter()
+            .filter(|id| !self.model_parts.contains(id))
+            .map(|&child| self.describe_node(child))
+            .collect()
+    }
+
+    fn describe_node(&self, id: NodeId) -> Result<NodeDescription, String> {
+        let node = &self.graph[id];
+        let model = self.model_instances.iter().find(|(i, _)| *i == id).map(|(_, m)| m.clone());
+        let mesh = if node.vao_id > 0 {
+            let mesh = self.meshes.iter().find(|m| m.vao.id() == node.vao_id)
+                .ok_or_else(|| format!("VAO {} was not loaded from the scene file", node.vao_id))?;
+            Some(mesh.description.name.clone())
+        } else {
+            None
+        };
+
+        Ok(NodeDescription {
+            name            : node.name.clone(),
+            mesh,
+            model,
+            position        : node.position.into(),
+            rotation        : node.rotation.into(),
+            scale           : node.scale.into(),
+            reference_point : node.reference_point.into(),
+            animation       : self.animations.iter().find(|(a, _)| *a == id).map(|(_, a)| a.clone()),
+            children        : self.describe_children(node.children())?,
+        })
+    }
+
+    // Writes the scene graph, as it currently is, to a scene file
+    #[allow(dead_code)]
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let config = ron::ser::PrettyConfig::new()
+            .extensions(ron::extensions::Extensions::IMPLICIT_SOME);
+        let source = ron::ser::to_string_pretty(&self.describe()?, config)
+            .map_err(|e| format!("Failed to serialize scene: {}", e))?;
+        std::fs::write(path, source)
+            .map_err(|e| format!("Failed to write scene file {}: {}", path, e))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lunar_scene_round_trips() {
+        let source = std::fs::read_to_string("./resources/lunar_scene.ron").unwrap();
+        let description: SceneDescription = ron::from_str(&source).unwrap();
+        assert_eq!(description.meshes.len(), 1);
+        assert_eq!(description.models[0].manifest, "./resources/helicopter.ron");
+        let helicopters = &description.nodes[0].children;
+        assert_eq!(helicopters.len(), 5);
+        assert_eq!(helicopters[2].model.as_deref(), Some("helicopter"));
+        assert_eq!(helicopters[2].animation, Some(Animation::Heading { time_offset: 1.5, altitude: 5.0 }));
+
+        let config = ron::ser::PrettyConfig::new()
+            .extensions(ron::extensions::Extensions::IMPLICIT_SOME);
+        let saved = ron::ser::to_string_pretty(&description, config).unwrap();
+        let reloaded: SceneDescription = ron::from_str(&saved).unwrap();
+        assert_eq!(format!("{:?}", reloaded), format!("{:?}", description));
+    }
+
+    #[test]
+    fn model_parts_are_instantiated_and_left_out_when_saving() {
+        let dir = std::env::temp_dir().join(format!("gloom-rs-scene-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let obj = dir.join("ship.obj");
+        std::fs::write(&obj, "o hull\nv 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\no fin\nv 0 0 1\nv 1 0 1\nv 0 1 1\nf 1 2 3\n").unwrap();
+        let manifest = dir.join("ship.ron");
+        std::fs::write(&manifest, format!(
+            "(path: {:?}, parts: [(object: \"hull\", role: \"hull\"), (object: \"fin\", role: \"fin\", parent: Some(\"hull\"), pivot: (1.0, 2.0, 3.0))])",
+            obj.to_str().unwrap(),
+        )).unwrap();
+
+        let description: SceneDescription = ron::from_str(&format!(
+            "(models: [(name: \"ship\", manifest: {:?})], nodes: [(name: Some(\"ship_0\"), model: Some(\"ship\"), position: (5.0, 0.0, 0.0))])",
+            manifest.to_str().unwrap(),
+        )).unwrap();
+        let mut next_vao = 0;
+        let scene = Scene::from_description(description, |_| { next_vao += 1; Ok(unsafe { VertexArray::from_raw(next_vao) }) }, |_| Ok(unsafe { Texture::from_raw(0) })).unwrap();
+
+        let fin = scene.graph.find("ship_0/hull/fin").unwrap();
+        assert_eq!(scene.graph[fin].reference_point, glm::vec3(1.0, 2.0, 3.0));
+        assert_eq!(scene.graph.world_position(fin), glm::vec3(5.0, 0.0, 0.0));
+        let layouts: Vec<String> = scene.vertex_layouts().into_iter().map(|(name, _, _)| name).collect();
+        assert_eq!(layouts, ["ship/hull", "ship/fin"]);
+
+        let saved = scene.describe().unwrap();
+        assert_eq!(saved.nodes.len(), 1);
+        assert_eq!(saved.nodes[0].model.as_deref(), Some("ship"));
+        assert_eq!(saved.nodes[0].position, [5.0, 0.0, 0.0]);
+        assert!(saved.nodes[0].children.is_empty());
+    }
+
+    #[test]
+    fn removed_nodes_are_no_longer_animated() {
+        let description: SceneDescription = ron::from_str(
+            "(nodes: [(name: Some(\"a\"), animation: Some(Spin(rates: (0.0, 1.0, 0.0)))), (name: Some(\"b\"), animation: Some(Spin(rates: (0.0, 1.0, 0.0))))])",
+        ).unwrap();
+        let mut scene = Scene::from_description(description, |_| unreachable!(), |_| unreachable!()).unwrap();
+        let a = scene.graph.find("a").unwrap();
+        let b = scene.graph.find("b").unwrap();
+
+        scene.graph.remove(a);
+        scene.animate(1.0, 1.0);
+        assert_eq!(scene.animations.len(), 2);
+
+        scene.remove(b);
+        scene.animate(2.0, 1.0);
+        assert!(scene.animations.is_empty());
+        assert!(scene.describe().unwrap().nodes.is_empty());
+    }
+
+    #[test]
+    fn normal_maps_give_meshes_tangents() {
+        let dir = std::env::temp_dir().join(format!("gloom-rs-normal-map-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let textured = dir.join("textured.obj");
+        std::fs::write(&textured, "v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0 0\nvt 1 0\nvt 0 1\nf 1/1 2/2 3/3\n").unwrap();
+        let plain = dir.join("plain.obj");
+        std::fs::write(&plain, "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n").unwrap();
+
+        let description = |path: &std::path::Path| -> SceneDescription {
+            ron::from_str(&format!(
+                "(meshes: [(name: \"ground\", path: {:?}, normal_map: Some(\"bumps.png\"))], nodes: [(mesh: Some(\"ground\"))])",
+                path.to_str().unwrap(),
+            )).unwrap()
+        };
+        let mut tangents = 0;
+        let scene = Scene::from_description(description(&textured), |mesh| { tangents = mesh.tangents.len(); Ok(unsafe { VertexArray::from_raw(1) }) }, |_| Ok(unsafe { Texture::from_raw(7) })).unwrap();
+        assert_eq!(tangents, 3 * 4);
+        let ground = scene.graph[scene.graph.root()].children()[0];
+        assert_eq!(scene.graph[ground].normal_map_id, 7);
+        assert_eq!(scene.textures.len(), 1);
+
+        let error = Scene::from_description(description(&plain), |_| Ok(unsafe { VertexArray::from_raw(1) }), |_| Ok(unsafe { Texture::from_raw(7) })).err().unwrap();
+        assert!(error.contains("no texture coordinates"), "{}", error);
+    }
+}
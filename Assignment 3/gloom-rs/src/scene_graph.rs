@@ -1,22 +1,32 @@
 extern crate nalgebra_glm as glm;
 
-use std::mem::ManuallyDrop;
-use std::pin::Pin;
-
-// Used to create an unholy abomination upon which you should not cast your gaze. This ended up
-// being a necessity due to wanting to keep the code written by students as "straight forward" as
-// possible. It is very very double plus ungood Rust, and intentionally leaks memory like a sieve.
-// But it works, and you're more than welcome to pretend it doesn't exist! In case you're curious
-// about how it works: It allocates memory on the heap (Box), promises to prevent it from being
-// moved or deallocated until dropped (Pin) and finally prevents the compiler from dropping it
-// automatically at all (ManuallyDrop).
-// ...
-// If that sounds like a janky solution, it's because it is!
-// Prettier, Rustier and better solutions were tried numerous times, but were all found wanting of
-// having what I arbitrarily decided to be the required level of "simplicity of use".
-pub type Node = ManuallyDrop<Pin<Box<SceneNode>>>;
+use std::cell::Cell;
+use std::ops::{Index, IndexMut};
+
+use crate::mesh::Material;
+
+// The scene graph owns every node in a flat arena, and nodes refer to each other through
+// `NodeId` handles rather than pointers. Removing a node drops it along with its entire subtree,
+// and the slot it lived in may later be reused. Each slot carries a generation counter, so a
+// handle to a removed node can never silently alias whatever node moves in afterwards.
+//
+// Nodes also cache their local and world matrices. Any mutable access to a node through the
+// graph marks it as changed and invalidates the world matrices of its whole subtree, so that
+// `SceneGraph::world_transform` only recomputes what actually moved since it was last asked.
+//
+// Nodes may be given a name, and then be looked up by their path from the root, e.g.
+// `graph.find("terrain/helicopter_3/main_rotor")`. A path segment of `*` matches any single node,
+// and `**` matches any number of levels, so `graph.find_all("**/door")` finds every door.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct NodeId {
+    index      : usize,
+    generation : u32,
+}
 
 pub struct SceneNode {
+    pub name            : Option<String>, // What I may be called
+
     pub position        : glm::Vec3,   // Where I should be in relation to my parent
     pub rotation        : glm::Vec3,   // How I should be rotated, around the X, the Y and the Z axes
     pub scale           : glm::Vec3,   // How I should be scaled
@@ -24,45 +34,92 @@ pub struct SceneNode {
 
     pub vao_id      : u32,             // What I should draw
     pub index_count : i32,             // How much of it there is to draw
+    pub material    : Material,        // What it should look like
+    pub texture_id  : u32,             // What it should be painted with
+    pub normal_map_id : u32,           // How bumpy it should look, in tangent space
+
+    parent   : Option<NodeId>,         // The one I answer to
+    children : Vec<NodeId>,            // Those I command
 
-    pub children: Vec<*mut SceneNode>, // Those I command
+    local_cache : Cell<glm::Mat4>,     // My last computed local_transform()
+    world_cache : Cell<glm::Mat4>,     // My last computed transformation into world space
+    local_dirty : Cell<bool>,          // Whether I have changed since local_cache was computed
+    world_dirty : Cell<bool>,          // Whether I or an ancestor has changed since world_cache was computed
 }
 
 impl SceneNode {
 
-    pub fn new() -> Node {
-        ManuallyDrop::new(Pin::new(Box::new(SceneNode {
+    pub fn new() -> SceneNode {
+        SceneNode {
+            name            : None,
             position        : glm::zero(),
             rotation        : glm::zero(),
             scale           : glm::vec3(1.0, 1.0, 1.0),
             reference_point : glm::zero(),
             vao_id          : 0,
             index_count     : -1,
+            material        : Material::default(),
+            texture_id      : 0,
+            normal_map_id   : 0,
+            parent          : None,
             children        : vec![],
-        })))
+            local_cache     : Cell::new(glm::identity()),
+            world_cache     : Cell::new(glm::identity()),
+            local_dirty     : Cell::new(true),
+            world_dirty     : Cell::new(true),
+        }
     }
 
-    pub fn from_vao(vao_id: u32, index_count: i32) -> Node {
-        ManuallyDrop::new(Pin::new(Box::new(SceneNode {
-            position        : glm::zero(),
-            rotation        : glm::zero(),
-            scale           : glm::vec3(1.0, 1.0, 1.0),
-            reference_point : glm::zero(),
+    pub fn from_vao(vao_id: u32, index_count: i32) -> SceneNode {
+        SceneNode {
             vao_id,
             index_count,
-            children: vec![],
-        })))
+            ..SceneNode::new()
+        }
     }
 
-    pub fn add_child(&mut self, child: &SceneNode) {
-        self.children.push(child as *const SceneNode as *mut SceneNode)
+    #[allow(dead_code)]
+    pub fn with_name(mut self, name: &str) -> SceneNode {
+        self.name = Some(name.to_string());
+        self
     }
 
-    #[allow(dead_code)]
-    pub fn get_child(& mut self, index: usize) -> & mut SceneNode {
-        unsafe {
-            &mut (*self.children[index])
+    // The transformation from my own coordinate space into my parent's. I am first scaled, then
+    // rotated around X, Y and Z in turn, all about my reference point, and finally moved to my
+    // position.
+    pub fn local_transform(&self) -> glm::Mat4 {
+        let mut transform = glm::translate(&glm::identity(), &self.position);
+
+        // Move pivot to reference point
+        transform = glm::translate(&transform, &self.reference_point);
+
+        // Apply rotations around Z, Y, X
+        transform = glm::rotate(&transform, self.rotation.z, &glm::vec3(0.0, 0.0, 1.0));
+        transform = glm::rotate(&transform, self.rotation.y, &glm::vec3(0.0, 1.0, 0.0));
+        transform = glm::rotate(&transform, self.rotation.x, &glm::vec3(1.0, 0.0, 0.0));
+
+        // Scale about the pivot
+        transform = glm::scale(&transform, &self.scale);
+
+        // Move pivot back
+        glm::translate(&transform, &-self.reference_point)
+    }
+
+    fn cached_local_transform(&self) -> glm::Mat4 {
+        if self.local_dirty.get() {
+            self.local_cache.set(self.local_transform());
+            self.local_dirty.set(false);
         }
+        self.local_cache.get()
+    }
+
+    #[allow(dead_code)]
+    pub fn parent(&self) -> Option<NodeId> {
+        self.parent
+    }
+
+    pub fn children(&self) -> &[NodeId] {
+        &self.children
     }
 
     #[allow(dead_code)]
@@ -74,13 +131,16 @@ impl SceneNode {
     pub fn print(&self) {
         println!(
 "SceneNode {{
+    Name:      {}
     VAO:       {}
     Indices:   {}
     Children:  {}
     Position:  [{:.2}, {:.2}, {:.2}]
     Rotation:  [{:.2}, {:.2}, {:.2}]
+    Scale:     [{:.2}, {:.2}, {:.2}]
     Reference: [{:.2}, {:.2}, {:.2}]
 }}",
+            self.name.as_deref().unwrap_or("<unnamed>"),
             self.vao_id,
             self.index_count,
             self.children.len(),
@@ -90,6 +150,9 @@ impl SceneNode {
             self.rotation.x,
             self.rotation.y,
             self.rotation.z,
+            self.scale.x,
+            self.scale.y,
+            self.scale.z,
             self.reference_point.x,
             self.reference_point.y,
             self.reference_point.z,
@@ -99,20 +162,452 @@ impl SceneNode {
 }
 
 
-// You can also use square brackets to access the children of a SceneNode
-use std::ops::{Index, IndexMut};
-impl Index<usize> for SceneNode {
-    type Output = SceneNode;
-    fn index(&self, index: usize) -> &SceneNode {
-        unsafe {
-            & *(self.children[index] as *const SceneNode)
+struct Slot {
+    generation : u32,
+    node       : Option<SceneNode>,
+}
+
+pub struct SceneGraph {
+    slots : Vec<Slot>,
+    free  : Vec<usize>,  // Indices of empty slots, ready for reuse
+    root  : NodeId,
+    count : usize,
+}
+
+impl SceneGraph {
+
+    // Creates a graph holding nothing but an empty root node
+    pub fn new() -> SceneGraph {
+        let mut graph = SceneGraph {
+            slots : vec![],
+            free  : vec![],
+            root  : NodeId { index: 0, generation: 0 },
+            count : 0,
+        };
+        graph.root = graph.insert(SceneNode::new());
+        graph
+    }
+
+    pub fn root(&self) -> NodeId {
+        self.root
+    }
+
+    // Number of live nodes, including the root
+    #[allow(dead_code)]
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn contains(&self, id: NodeId) -> bool {
+        self.get(id).is_some()
+    }
+
+    pub fn get(&self, id: NodeId) -> Option<&SceneNode> {
+        match self.slots.get(id.index) {
+            Some(slot) if slot.generation == id.generation => slot.node.as_ref(),
+            _ => None,
+        }
+    }
+
+    // Mutable access assumes the node is about to change, and invalidates its cached transforms
+    pub fn get_mut(&mut self, id: NodeId) -> Option<&mut SceneNode> {
+        if self.contains(id) {
+            self.invalidate(id);
+        }
+        match self.slots.get_mut(id.index) {
+            Some(slot) if slot.generation == id.generation => slot.node.as_mut(),
+            _ => None,
+        }
+    }
+
+    // Moves `node` into the graph as the last child of `parent`, and returns its handle
+    pub fn add_child(&mut self, parent: NodeId, mut node: SceneNode) -> NodeId {
+        assert!(self.contains(parent), "Parent {:?} is not in the scene graph", parent);
+        node.parent = Some(parent);
+        node.children.clear();
+        node.local_dirty.set(true);
+        node.world_dirty.set(true);
+        let id = self.insert(node);
+        self.node_mut(parent).children.push(id);
+        id
+    }
+
+    // Returns the `index`-th child of `parent`
+    #[allow(dead_code)]
+    pub fn get_child(&self, parent: NodeId, index: usize) -> NodeId {
+        self[parent].children[index]
+    }
+
+    // Removes a node and drops its entire subtree. Returns the removed node itself, detached
+    // and without children, or None if the handle was already stale.
+    #[allow(dead_code)]
+    pub fn remove(&mut self, id: NodeId) -> Option<SceneNode> {
+        assert!(id != self.root, "The root node cannot be removed");
+        let parent = self.get(id)?.parent;
+        if let Some(parent) = parent {
+            self.node_mut(parent).children.retain(|&c| c != id);
+        }
+
+        let mut node = self.take(id);
+        let mut stack = std::mem::take(&mut node.children);
+        while let Some(descendant) = stack.pop() {
+            stack.extend(self.take(descendant).children);
         }
+        node.parent = None;
+        Some(node)
+    }
+
+    // Moves a node, along with its subtree, to become the last child of `new_parent`
+    #[allow(dead_code)]
+    pub fn reparent(&mut self, id: NodeId, new_parent: NodeId) -> Result<(), String> {
+        if id == self.root {
+            return Err("The root node cannot be reparented".to_string());
+        }
+        if !self.contains(id) || !self.contains(new_parent) {
+            return Err(format!("Cannot reparent {:?} to {:?}: stale node handle", id, new_parent));
+        }
+        if self.is_ancestor(id, new_parent) {
+            return Err(format!("Cannot reparent {:?} into its own subtree", id));
+        }
+
+        // Only the moved subtree ends up somewhere else in the world
+        self.invalidate(id);
+        if let Some(old_parent) = self[id].parent {
+            self.node_mut(old_parent).children.retain(|&c| c != id);
+        }
+        self.node_mut(id).parent = Some(new_parent);
+        self.node_mut(new_parent).children.push(id);
+        Ok(())
+    }
+
+    // The transformation from a node's coordinate space into world space. Only the nodes along
+    // the path from the root which changed since the last call are recomputed.
+    pub fn world_transform(&self, id: NodeId) -> glm::Mat4 {
+        // Climb to the nearest ancestor whose world transform is still valid, then come back down
+        let mut stale = vec![];
+        let mut current = Some(id);
+        while let Some(node_id) = current {
+            let node = &self[node_id];
+            if !node.world_dirty.get() {
+                break;
+            }
+            stale.push(node_id);
+            current = node.parent;
+        }
+        let mut transform = match current {
+            Some(valid) => self[valid].world_cache.get(),
+            None => glm::identity(),
+        };
+        for &node_id in stale.iter().rev() {
+            let node = &self[node_id];
+            transform *= node.cached_local_transform();
+            node.world_cache.set(transform);
+            node.world_dirty.set(false);
+        }
+        transform
+    }
+
+    // Where a node's origin ends up in world space
+    #[allow(dead_code)]
+    pub fn world_position(&self, id: NodeId) -> glm::Vec3 {
+        let transform = self.world_transform(id);
+        glm::vec3(transform[(0, 3)], transform[(1, 3)], transform[(2, 3)])
+    }
+
+    // Looks up the first node matching a path relative to the root
+    #[allow(dead_code)]
+    pub fn find(&self, path: &str) -> Option<NodeId> {
+        self.find_from(self.root, path)
+    }
+
+    // Looks up every node matching a path relative to the root, in depth-first order
+    pub fn find_all(&self, path: &str) -> Vec<NodeId> {
+        self.find_all_from(self.root, path)
+    }
+
+    #[allow(dead_code)]
+    pub fn find_from(&self, start: NodeId, path: &str) -> Option<NodeId> {
+        self.find_all_from(start, path).first().copied()
+    }
+
+    pub fn find_all_from(&self, start: NodeId, path: &str) -> Vec<NodeId> {
+        let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+        let mut found = vec![];
+        self.match_path(start, &segments, &mut found);
+        found
+    }
+
+    fn match_path(&self, id: NodeId, segments: &[&str], found: &mut Vec<NodeId>) {
+        let (segment, rest) = match segments.split_first() {
+            Some(split) => split,
+            None => {
+                // `**` can reach the same node along several routes
+                if !found.contains(&id) {
+                    found.push(id);
+                }
+                return;
+            }
+        };
+        if *segment == "**" {
+            self.match_path(id, rest, found);
+        }
+        for &child in &self[id].children {
+            match *segment {
+                "**" => self.match_path(child, segments, found),
+                "*" => self.match_path(child, rest, found),
+                name if self[child].name.as_deref() == Some(name) => self.match_path(child, rest, found),
+                _ => {}
+            }
+        }
+    }
+
+    // Prints a node and everything below it as an indented tree
+    #[allow(dead_code)]
+    pub fn print(&self, id: NodeId) {
+        self.print_indented(id, 0);
+    }
+
+    fn print_indented(&self, id: NodeId, depth: usize) {
+        let node = &self[id];
+        let name = node.name.as_deref().unwrap_or(if id == self.root { "<root>" } else { "<unnamed>" });
+        if node.vao_id > 0 && node.index_count > 0 {
+            println!("{:indent$}{} (VAO {}, {} indices)", "", name, node.vao_id, node.index_count, indent = 2 * depth);
+        } else {
+            println!("{:indent$}{}", "", name, indent = 2 * depth);
+        }
+        for &child in &node.children {
+            self.print_indented(child, depth + 1);
+        }
+    }
+
+    // Whether `ancestor` is `id` itself, or lies somewhere above it in the tree
+    pub fn is_ancestor(&self, ancestor: NodeId, id: NodeId) -> bool {
+        let mut current = Some(id);
+        while let Some(node) = current {
+            if node == ancestor {
+                return true;
+            }
+            current = self.get(node).and_then(|n| n.parent);
+        }
+        false
+    }
+
+    fn insert(&mut self, node: SceneNode) -> NodeId {
+        self.count += 1;
+        if let Some(index) = self.free.pop() {
+            let slot = &mut self.slots[index];
+            slot.node = Some(node);
+            NodeId { index, generation: slot.generation }
+        } else {
+            self.slots.push(Slot { generation: 0, node: Some(node) });
+            NodeId { index: self.slots.len() - 1, generation: 0 }
+        }
+    }
+
+    // Marks a node as changed, and the world transforms of its entire subtree as stale. A node
+    // whose world transform is already stale is known to have a stale subtree too.
+    fn invalidate(&self, id: NodeId) {
+        self[id].local_dirty.set(true);
+        let mut stack = vec![id];
+        while let Some(current) = stack.pop() {
+            let node = &self[current];
+            if !node.world_dirty.replace(true) {
+                stack.extend_from_slice(&node.children);
+            }
+        }
+    }
+
+    // Mutable access for changing how nodes are linked, which leaves the cached transforms alone
+    fn node_mut(&mut self, id: NodeId) -> &mut SceneNode {
+        match self.slots.get_mut(id.index) {
+            Some(slot) if slot.generation == id.generation => slot.node.as_mut(),
+            _ => None,
+        }.expect("Invalid or removed NodeId")
+    }
+
+    fn take(&mut self, id: NodeId) -> SceneNode {
+        let slot = &mut self.slots[id.index];
+        let node = slot.node.take().expect("Scene graph slot was already empty");
+        slot.generation = slot.generation.wrapping_add(1);
+        self.free.push(id.index);
+        self.count -= 1;
+        node
+    }
+
+}
+
+
+// You can use square brackets with a NodeId to access the nodes of a SceneGraph
+impl Index<NodeId> for SceneGraph {
+    type Output = SceneNode;
+    fn index(&self, id: NodeId) -> &SceneNode {
+        self.get(id).expect("Invalid or removed NodeId")
+    }
+}
+impl IndexMut<NodeId> for SceneGraph {
+    fn index_mut(&mut self, id: NodeId) -> &mut SceneNode {
+        self.get_mut(id).expect("Invalid or removed NodeId")
     }
 }
-impl IndexMut<usize> for SceneNode {
-    fn index_mut(&mut self, index: usize) -> &mut SceneNode {
-        unsafe {
-            &mut (*self.children[index])
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::FRAC_PI_2;
+
+    fn assert_mat_eq(actual: &glm::Mat4, expected: &glm::Mat4) {
+        assert!(
+            (actual - expected).abs().max() < 1e-5,
+            "matrices differ\nactual:   {}\nexpected: {}", actual, expected,
+        );
+    }
+
+    fn transform_point(m: &glm::Mat4, p: glm::Vec3) -> glm::Vec3 {
+        (m * glm::vec4(p.x, p.y, p.z, 1.0)).xyz()
+    }
+
+    #[test]
+    fn default_node_is_identity() {
+        assert_mat_eq(&SceneNode::new().local_transform(), &glm::identity());
+    }
+
+    #[test]
+    fn translation_only() {
+        let mut node = SceneNode::new();
+        node.position = glm::vec3(1.0, 2.0, 3.0);
+        let expected = glm::mat4(
+            1.0, 0.0, 0.0, 1.0,
+            0.0, 1.0, 0.0, 2.0,
+            0.0, 0.0, 1.0, 3.0,
+            0.0, 0.0, 0.0, 1.0,
+        );
+        assert_mat_eq(&node.local_transform(), &expected);
+    }
+
+    #[test]
+    fn non_uniform_scale_about_reference_point() {
+        let mut node = SceneNode::new();
+        node.scale = glm::vec3(2.0, 3.0, 4.0);
+        node.reference_point = glm::vec3(1.0, 1.0, 1.0);
+        // x' = 2(x - 1) + 1, y' = 3(y - 1) + 1, z' = 4(z - 1) + 1
+        let expected = glm::mat4(
+            2.0, 0.0, 0.0, -1.0,
+            0.0, 3.0, 0.0, -2.0,
+            0.0, 0.0, 4.0, -3.0,
+            0.0, 0.0, 0.0,  1.0,
+        );
+        assert_mat_eq(&node.local_transform(), &expected);
+        assert_eq!(transform_point(&expected, node.reference_point), node.reference_point);
+    }
+
+    #[test]
+    fn rotation_about_reference_point() {
+        let mut node = SceneNode::new();
+        node.rotation = glm::vec3(0.0, 0.0, FRAC_PI_2);
+        node.reference_point = glm::vec3(1.0, 0.0, 0.0);
+        // A quarter turn around Z through (1, 0, 0): (x, y) -> (1 - y, x - 1)
+        let expected = glm::mat4(
+            0.0, -1.0, 0.0,  1.0,
+            1.0,  0.0, 0.0, -1.0,
+            0.0,  0.0, 1.0,  0.0,
+            0.0,  0.0, 0.0,  1.0,
+        );
+        assert_mat_eq(&node.local_transform(), &expected);
+    }
+
+    #[test]
+    fn rotations_apply_x_then_y_then_z() {
+        let mut node = SceneNode::new();
+        node.rotation = glm::vec3(FRAC_PI_2, FRAC_PI_2, 0.0);
+        // X first takes +Y to +Z, then Y takes +Z to +X
+        let p = transform_point(&node.local_transform(), glm::vec3(0.0, 1.0, 0.0));
+        assert!((p - glm::vec3(1.0, 0.0, 0.0)).norm() < 1e-5, "got {}", p);
+    }
+
+    #[test]
+    fn scale_is_applied_before_rotation_and_translation() {
+        let mut node = SceneNode::new();
+        node.position = glm::vec3(10.0, 0.0, 0.0);
+        node.rotation = glm::vec3(0.0, 0.0, FRAC_PI_2);
+        node.scale = glm::vec3(2.0, 1.0, 1.0);
+        // (1, 0, 0) -> scaled (2, 0, 0) -> rotated (0, 2, 0) -> moved (10, 2, 0)
+        let expected = glm::mat4(
+            0.0, -1.0, 0.0, 10.0,
+            2.0,  0.0, 0.0,  0.0,
+            0.0,  0.0, 1.0,  0.0,
+            0.0,  0.0, 0.0,  1.0,
+        );
+        assert_mat_eq(&node.local_transform(), &expected);
+    }
+
+    #[test]
+    fn world_transform_follows_changed_ancestors() {
+        let mut graph = SceneGraph::new();
+        let parent = graph.add_child(graph.root(), SceneNode::new());
+        let child = graph.add_child(parent, SceneNode::new());
+        graph[child].position = glm::vec3(0.0, 1.0, 0.0);
+        assert_eq!(graph.world_position(child), glm::vec3(0.0, 1.0, 0.0));
+
+        graph[parent].position = glm::vec3(5.0, 0.0, 0.0);
+        assert_eq!(graph.world_position(child), glm::vec3(5.0, 1.0, 0.0));
+
+        let other = graph.add_child(graph.root(), SceneNode::new());
+        graph[other].position = glm::vec3(0.0, 0.0, -2.0);
+        graph.reparent(child, other).unwrap();
+        assert_eq!(graph.world_position(child), glm::vec3(0.0, 1.0, -2.0));
+    }
+
+    #[test]
+    fn unchanged_subtrees_keep_their_cache() {
+        let mut graph = SceneGraph::new();
+        let a = graph.add_child(graph.root(), SceneNode::new());
+        let b = graph.add_child(graph.root(), SceneNode::new());
+        let b_child = graph.add_child(b, SceneNode::new());
+        graph.world_transform(a);
+        graph.world_transform(b_child);
+
+        graph[a].rotation.y = 1.0;
+        assert!(graph[a].world_dirty.get());
+        assert!(!graph[b].world_dirty.get());
+        assert!(!graph[b_child].world_dirty.get());
+
+        // Attaching a child leaves its parent and siblings as they were
+        graph.add_child(b, SceneNode::new());
+        assert!(!graph[b].world_dirty.get());
+        assert!(!graph[b_child].world_dirty.get());
+    }
+
+    #[test]
+    fn deep_hierarchies_do_not_overflow_the_stack() {
+        let mut graph = SceneGraph::new();
+        let mut leaf = graph.root();
+        for _ in 0..20_000 {
+            leaf = graph.add_child(leaf, SceneNode::new());
+            graph[leaf].position = glm::vec3(0.0, 1.0, 0.0);
         }
+        assert_eq!(graph.world_position(leaf), glm::vec3(0.0, 20_000.0, 0.0));
+    }
+
+    #[test]
+    fn find_by_path_and_wildcards() {
+        let mut graph = SceneGraph::new();
+        let terrain = graph.add_child(graph.root(), SceneNode::new().with_name("terrain"));
+        let mut doors = vec![];
+        for i in 0..3 {
+            let helicopter = graph.add_child(terrain, SceneNode::new().with_name(&format!("helicopter_{}", i)));
+            graph.add_child(helicopter, SceneNode::new().with_name("main_rotor"));
+            doors.push(graph.add_child(helicopter, SceneNode::new().with_name("door")));
+        }
+
+        let rotor = graph.find("terrain/helicopter_1/main_rotor").unwrap();
+        assert_eq!(graph[rotor].name.as_deref(), Some("main_rotor"));
+        assert_eq!(graph.find("helicopter_1/main_rotor"), None);
+        assert_eq!(graph.find_from(terrain, "helicopter_1/main_rotor"), Some(rotor));
+
+        assert_eq!(graph.find_all("terrain/*/door"), doors);
+        assert_eq!(graph.find_all("**/door"), doors);
+        assert_eq!(graph.find_all("**/**/door"), doors);
+        assert!(graph.find_all("*/door").is_empty());
     }
 }
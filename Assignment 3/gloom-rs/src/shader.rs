@@ -1,38 +1,468 @@
+extern crate nalgebra_glm as glm;
+
 use gl;
 use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    fmt,
     ptr,
     str,
     ffi::CString,
     path::Path,
+    time::SystemTime,
 };
 
+use crate::gpu::Program;
+use crate::preprocessor::{self, PreprocessedSource};
+use crate::reflection::{self, ActiveAttribute, ActiveUniform};
+use crate::vertex::VertexLayout;
+
 pub struct Shader {
-    pub program_id: u32,
+    pub program: Program,
+    diagnostics: Vec<Diagnostics>,
+    sources: Vec<ShaderSource>,
+    attributes: Vec<ActiveAttribute>,
+    uniform_info: Vec<ActiveUniform>,
+    uniforms: UniformLocations,
 }
 
 pub struct ShaderBuilder {
-    program_id: u32,
+    program: Program,
     shaders: Vec::<u32>,
+    diagnostics: Vec<Diagnostics>,
+    sources: Vec<ShaderSource>,
+    defines: Vec<(String, String)>,
+}
+
+// What one stage was compiled from, so the program can be built again when its files change
+#[derive(Clone, Debug)]
+struct ShaderSource {
+    shader_type : ShaderType,
+    path        : Option<String>,                        // None for source passed in directly
+    source      : String,
+    defines     : Vec<(String, String)>,
+    files       : Vec<(String, Option<SystemTime>)>,     // Every file read, and when it was last changed
+}
+
+impl ShaderSource {
+    fn has_changed(&self) -> bool {
+        self.files.iter().any(|(path, modified)| preprocessor::modification_time(path) != *modified)
+    }
+}
+
+// The location of every active uniform, looked up once after linking. Arrays can be found both
+// by their plain name and as `name[0]`.
+#[derive(Debug, Default)]
+struct UniformLocations {
+    locations : HashMap<String, i32>,
+    warned    : RefCell<HashSet<String>>,  // Unknown names that have been complained about
+}
+
+impl UniformLocations {
+    fn of_uniforms(uniforms: &[ActiveUniform]) -> UniformLocations {
+        let mut locations = HashMap::new();
+        // Members of uniform blocks have no location of their own
+        for uniform in uniforms.iter().filter(|u| u.location >= 0) {
+            if let Some(array) = uniform.name.strip_suffix("[0]") {
+                locations.insert(array.to_string(), uniform.location);
+            }
+            locations.insert(uniform.name.clone(), uniform.location);
+        }
+        UniformLocations { locations, warned: RefCell::default() }
+    }
+
+    // Uniforms that are misspelled, or unused and therefore optimized away, are only reported once
+    fn get(&self, name: &str) -> Option<i32> {
+        let location = self.locations.get(name).copied();
+        if location.is_none() && self.warned.borrow_mut().insert(name.to_string()) {
+            println!("Warning: the shader has no active uniform named {}", name);
+        }
+        location
+    }
 }
 
 #[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum ShaderType {
     Vertex,
     Fragment,
     TessellationControl,
     TessellationEvaluation,
     Geometry,
+    Compute,
+}
+
+// Errors
+
+#[derive(Debug)]
+pub enum ShaderError {
+    Io { path: String, error: std::io::Error },
+    UnknownExtension { path: String },
+    Compile { stage: ShaderType, path: Option<String>, log: ShaderLog },
+    Link { log: ShaderLog },
+    Include { path: String, line: u32, message: String },
+    WrongStages { expected: Vec<ShaderType>, found: Vec<ShaderType> },
+    NulByte { what: String },
+}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ShaderError::Io { path, error } => {
+                write!(f, "Failed to read shader source {}: {}", path, error)
+            }
+            ShaderError::UnknownExtension { path } => {
+                write!(f, "Cannot tell what kind of shader {} is from its extension", path)
+            }
+            ShaderError::Compile { stage, path, log } => {
+                writeln!(f, "Failed to compile {:?} shader {}:", stage, path.as_deref().unwrap_or("from source"))?;
+                write!(f, "{}", log)
+            }
+            ShaderError::Link { log } => {
+                writeln!(f, "Failed to link shader program:")?;
+                write!(f, "{}", log)
+            }
+            ShaderError::Include { path, line, message } => {
+                write!(f, "Failed to preprocess shader {}:{}: {}", path, line, message)
+            }
+            ShaderError::WrongStages { expected, found } => {
+                write!(f, "Expected a program built from {:?} shaders, but it was built from {:?}", expected, found)
+            }
+            ShaderError::NulByte { what } => {
+                write!(f, "{} contains a NUL byte, which OpenGL cannot be given", what)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ShaderError {}
+
+// Text to hand to OpenGL, which reads it up to the first NUL byte
+pub(crate) fn c_string(text: &str, what: impl FnOnce() -> String) -> Result<CString, ShaderError> {
+    CString::new(text).map_err(|_| ShaderError::NulByte { what: what() })
+}
+
+// Everything the driver had to say about one stage, or about linking, when it succeeded
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostics {
+    pub stage : Option<ShaderType>,   // None for the link step
+    pub path  : Option<String>,
+    pub log   : ShaderLog,
+}
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.stage {
+            Some(stage) => writeln!(f, "{:?} shader {}:", stage, self.path.as_deref().unwrap_or("from source"))?,
+            None => writeln!(f, "Shader program:")?,
+        }
+        write!(f, "{}", self.log)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+    Info,
+}
+
+// One line of a compile or link log. Drivers word their logs differently, but they all point at
+// a source string and a line within it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LogMessage {
+    pub severity    : Severity,
+    pub file        : Option<u32>,     // Which source string, as numbered by the driver
+    pub line        : Option<u32>,
+    pub text        : String,
+    pub path        : Option<String>,  // The file the source string was read from, if known
+    pub source_line : Option<String>,  // The offending line itself, if known
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ShaderLog {
+    pub raw      : String,
+    pub messages : Vec<LogMessage>,
+}
+
+impl ShaderLog {
+    // Understands the formats of Mesa (`0:12(5): error: ...`), NVIDIA (`0(12) : error C1008: ...`)
+    // and AMD/Intel (`ERROR: 0:12: ...`). Anything else is kept as a message without a location.
+    pub fn parse(raw: &str) -> ShaderLog {
+        ShaderLog {
+            raw      : raw.to_string(),
+            messages : raw.lines().filter_map(parse_log_line).collect(),
+        }
+    }
+
+    // Fills in the path and source line of every message, given the path and contents of each
+    // source string in the order the driver numbers them
+    pub fn annotate(&mut self, sources: &[(Option<String>, &str)]) {
+        for message in &mut self.messages {
+            let (path, source) = match message.file.and_then(|file| sources.get(file as usize)) {
+                Some(source) => source,
+                None => continue,
+            };
+            message.path = path.clone();
+            message.source_line = message.line
+                .and_then(|line| source.lines().nth((line as usize).checked_sub(1)?))
+                .map(|line| line.to_string());
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn has_errors(&self) -> bool {
+        self.messages.iter().any(|m| m.severity == Severity::Error)
+    }
+}
+
+impl fmt::Display for ShaderLog {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for message in &self.messages {
+            write!(f, "  ")?;
+            match (&message.path, message.file) {
+                (Some(path), _) => write!(f, "{}:", path)?,
+                (None, Some(file)) => write!(f, "{}:", file)?,
+                (None, None) => {}
+            }
+            if let Some(line) = message.line {
+                write!(f, "{}: ", line)?;
+            } else if message.file.is_some() {
+                write!(f, " ")?;
+            }
+            writeln!(f, "{:?}: {}", message.severity, message.text)?;
+            if let Some(source_line) = &message.source_line {
+                writeln!(f, "      | {}", source_line.trim_end())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn parse_log_line(line: &str) -> Option<LogMessage> {
+    let line = line.trim();
+    if line.is_empty() {
+        return None;
+    }
+    let message = |severity, file, line, text: &str| Some(LogMessage {
+        severity,
+        file,
+        line,
+        text        : text.trim().to_string(),
+        path        : None,
+        source_line : None,
+    });
+
+    // AMD and Intel put the severity first
+    for &(prefix, severity) in &[("ERROR:", Severity::Error), ("WARNING:", Severity::Warning)] {
+        if let Some(rest) = line.strip_prefix(prefix) {
+            return match parse_location(rest.trim_start()) {
+                Some((file, line, text)) => message(severity, Some(file), Some(line), text),
+                None => message(severity, None, None, rest),
+            };
+        }
+    }
+
+    // Mesa and NVIDIA put the location first
+    if let Some((file, line, rest)) = parse_location(line) {
+        let (severity, text) = split_severity(rest);
+        return message(severity, Some(file), Some(line), text);
+    }
+    let (severity, text) = split_severity(line);
+    message(severity, None, None, text)
+}
+
+// Reads `file:line`, `file:line(column)` or `file(line)`, followed by a colon
+fn parse_location(s: &str) -> Option<(u32, u32, &str)> {
+    let (file, rest) = number(s)?;
+    let (line, rest) = match rest.strip_prefix(':') {
+        Some(rest) => {
+            let (line, rest) = number(rest)?;
+            match rest.strip_prefix('(') {
+                Some(column) => (line, number(column)?.1.strip_prefix(')')?),
+                None => (line, rest),
+            }
+        }
+        None => {
+            let (line, rest) = number(rest.strip_prefix('(')?)?;
+            (line, rest.strip_prefix(')')?)
+        }
+    };
+    Some((file, line, rest.trim_start().strip_prefix(':')?))
+}
+
+// Reads the number at the start of `s`, and returns what follows it
+fn number(s: &str) -> Option<(u32, &str)> {
+    let end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    Some((s[..end].parse().ok()?, &s[end..]))
+}
+
+// Splits `error: text` or `error C1008: text` into its severity and text
+fn split_severity(s: &str) -> (Severity, &str) {
+    let s = s.trim();
+    let lower = s.to_ascii_lowercase();
+    let (severity, word) = if lower.starts_with("error") {
+        (Severity::Error, "error".len())
+    } else if lower.starts_with("warning") {
+        (Severity::Warning, "warning".len())
+    } else {
+        return (Severity::Info, s);
+    };
+    let rest = &s[word..];
+    match rest.find(':') {
+        Some(colon) if rest[..colon].split_whitespace().count() <= 1 => (severity, &rest[colon + 1..]),
+        _ => (severity, rest),
+    }
 }
 
 impl Shader {
-    // Make sure the shader is active before calling this
-    pub unsafe fn get_uniform_location(&self, name: &str) -> i32 {
-        let name_cstr = CString::new(name).expect("CString::new failed");
-        gl::GetUniformLocation(self.program_id, name_cstr.as_ptr())
+    // What the driver said while compiling and linking the program, which is usually warnings
+    pub fn diagnostics(&self) -> &[Diagnostics] {
+        &self.diagnostics
+    }
+
+    pub fn warnings(&self) -> impl Iterator<Item = &LogMessage> {
+        self.diagnostics.iter()
+            .flat_map(|d| d.log.messages.iter())
+            .filter(|m| m.severity == Severity::Warning)
+    }
+
+    // Builds the program again if any of its files changed on disk since it was built, and
+    // returns whether it did. If the new version fails to build, the old program is kept and
+    // the error returned, and nothing is tried again until a file changes once more.
+    pub unsafe fn reload_if_changed(&mut self) -> Option<Result<(), ShaderError>> {
+        if !self.sources.iter().any(|source| source.has_changed()) {
+            return None;
+        }
+        for source in &mut self.sources {
+            for (path, modified) in &mut source.files {
+                *modified = preprocessor::modification_time(path);
+            }
+        }
+
+        let mut builder = ShaderBuilder::new();
+        for source in &self.sources {
+            builder.defines = source.defines.clone();
+            let attached = match &source.path {
+                Some(path) => builder.attach_file(path),
+                None => builder.compile_shader(&source.source, source.shader_type),
+            };
+            builder = match attached {
+                Ok(builder) => builder,
+                Err(e) => return Some(Err(e)),
+            };
+        }
+        Some(builder.link().map(|shader| *self = shader))
+    }
+
+    // The vertex attributes the program reads, ordered by location
+    #[allow(dead_code)]
+    pub fn attributes(&self) -> &[ActiveAttribute] {
+        &self.attributes
+    }
+
+    // Every uniform the program uses, ordered by name
+    #[allow(dead_code)]
+    pub fn uniforms(&self) -> &[ActiveUniform] {
+        &self.uniform_info
+    }
+
+    // Describes every way a VAO with this layout does not match what the program reads, apart
+    // from leaving out the `optional` attributes
+    pub fn check_vertex_layout(&self, layout: &VertexLayout, optional: &[&str]) -> Vec<String> {
+        reflection::check_vertex_layout(&self.attributes, layout, optional)
+    }
+
+    // The stages the program was built from, in the order they were attached
+    #[allow(dead_code)]
+    pub fn stages(&self) -> Vec<ShaderType> {
+        self.sources.iter().map(|source| source.shader_type).collect()
+    }
+
+    // Connects `buffer Name { ... }` in the shaders to the SSBO bound at `binding`, for blocks
+    // declared without a `layout(binding = ...)`. Returns false if the program has no such block.
+    #[allow(dead_code)]
+    pub unsafe fn bind_storage_block(&self, name: &str, binding: u32) -> Result<bool, ShaderError> {
+        let name_cstr = c_string(name, || format!("Storage block name {:?}", name))?;
+        let index = gl::GetProgramResourceIndex(self.program.id(), gl::SHADER_STORAGE_BLOCK, name_cstr.as_ptr());
+        if index == gl::INVALID_INDEX {
+            return Ok(false);
+        }
+        gl::ShaderStorageBlockBinding(self.program.id(), index, binding);
+        Ok(true)
+    }
+
+    // The cached location of a uniform, or -1 if the shader does not have it
+    #[allow(dead_code)]
+    pub fn get_uniform_location(&self, name: &str) -> i32 {
+        self.uniforms.get(name).unwrap_or(-1)
+    }
+
+    // The setters write straight to the program, which does not have to be active. Uniforms the
+    // program does not have are skipped, with a warning the first time.
+
+    pub unsafe fn set_mat4(&self, name: &str, value: &glm::Mat4) {
+        if let Some(location) = self.uniforms.get(name) {
+            gl::ProgramUniformMatrix4fv(self.program.id(), location, 1, gl::FALSE, value.as_ptr());
+        }
+    }
+
+    #[allow(dead_code)]
+    pub unsafe fn set_vec4(&self, name: &str, value: &glm::Vec4) {
+        if let Some(location) = self.uniforms.get(name) {
+            gl::ProgramUniform4fv(self.program.id(), location, 1, value.as_ptr());
+        }
+    }
+
+    pub unsafe fn set_vec3(&self, name: &str, value: &glm::Vec3) {
+        if let Some(location) = self.uniforms.get(name) {
+            gl::ProgramUniform3fv(self.program.id(), location, 1, value.as_ptr());
+        }
+    }
+
+    pub unsafe fn set_f32(&self, name: &str, value: f32) {
+        if let Some(location) = self.uniforms.get(name) {
+            gl::ProgramUniform1f(self.program.id(), location, value);
+        }
+    }
+
+    // Also used for booleans and samplers
+    pub unsafe fn set_i32(&self, name: &str, value: i32) {
+        if let Some(location) = self.uniforms.get(name) {
+            gl::ProgramUniform1i(self.program.id(), location, value);
+        }
+    }
+
+    #[allow(dead_code)]
+    pub unsafe fn set_mat4_array(&self, name: &str, values: &[glm::Mat4]) {
+        if let Some(location) = self.uniforms.get(name) {
+            gl::ProgramUniformMatrix4fv(self.program.id(), location, values.len() as i32, gl::FALSE, values.as_ptr() as *const f32);
+        }
+    }
+
+    #[allow(dead_code)]
+    pub unsafe fn set_vec3_array(&self, name: &str, values: &[glm::Vec3]) {
+        if let Some(location) = self.uniforms.get(name) {
+            gl::ProgramUniform3fv(self.program.id(), location, values.len() as i32, values.as_ptr() as *const f32);
+        }
+    }
+
+    #[allow(dead_code)]
+    pub unsafe fn set_f32_array(&self, name: &str, values: &[f32]) {
+        if let Some(location) = self.uniforms.get(name) {
+            gl::ProgramUniform1fv(self.program.id(), location, values.len() as i32, values.as_ptr());
+        }
+    }
+
+    #[allow(dead_code)]
+    pub unsafe fn set_i32_array(&self, name: &str, values: &[i32]) {
+        if let Some(location) = self.uniforms.get(name) {
+            gl::ProgramUniform1iv(self.program.id(), location, values.len() as i32, values.as_ptr());
+        }
     }
 
     pub unsafe fn activate(&self) {
-        gl::UseProgram(self.program_id);
+        gl::UseProgram(self.program.id());
     }
 }
 
@@ -44,18 +474,21 @@ impl Into<gl::types::GLenum> for ShaderType {
             ShaderType::TessellationControl     => { gl::TESS_CONTROL_SHADER    },
             ShaderType::TessellationEvaluation  => { gl::TESS_EVALUATION_SHADER } ,
             ShaderType::Geometry                => { gl::GEOMETRY_SHADER        },
+            ShaderType::Compute                 => { gl::COMPUTE_SHADER         },
         }
     }
 }
 
 impl ShaderType {
     fn from_ext(ext: &std::ffi::OsStr) -> Result<ShaderType, String> {
-        match ext.to_str().expect("Failed to read extension") {
+        let ext = ext.to_str().ok_or_else(|| ext.to_string_lossy().into_owned())?;
+        match ext {
             "vert" => { Ok(ShaderType::Vertex) },
             "frag" => { Ok(ShaderType::Fragment) },
             "tcs"  => { Ok(ShaderType::TessellationControl) },
             "tes"  => { Ok(ShaderType::TessellationEvaluation) },
             "geom" => { Ok(ShaderType::Geometry) },
+            "comp" => { Ok(ShaderType::Compute) },
             e => { Err(e.to_string()) },
         }
     }
@@ -64,91 +497,239 @@ impl ShaderType {
 impl ShaderBuilder {
     pub unsafe fn new() -> ShaderBuilder {
         ShaderBuilder {
-            program_id: gl::CreateProgram(),
+            program: Program::new(),
             shaders: vec![],
+            diagnostics: vec![],
+            sources: vec![],
+            defines: vec![],
         }
     }
 
-    pub unsafe fn attach_file(self, shader_path: &str) -> ShaderBuilder {
+    // Adds `#define name value` right after the `#version` of every shader attached from here on
+    #[allow(dead_code)]
+    pub fn define(mut self, name: &str, value: &str) -> ShaderBuilder {
+        self.defines.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    // Reads a shader file, along with any files it includes
+    pub unsafe fn attach_file(self, shader_path: &str) -> Result<ShaderBuilder, ShaderError> {
         let path = Path::new(shader_path);
-        if let Some(extension) = path.extension() {
-            let shader_type = ShaderType::from_ext(extension)
-                .expect("Failed to parse file extension.");
-            let shader_src = std::fs::read_to_string(path)
-                .expect(&format!("Failed to read shader source. {}", shader_path));
-            self.compile_shader(&shader_src, shader_type)
-        } else {
-            panic!("Failed to read extension of file with path: {}", shader_path);
-        }
+        let shader_type = path.extension()
+            .and_then(|extension| ShaderType::from_ext(extension).ok())
+            .ok_or_else(|| ShaderError::UnknownExtension { path: shader_path.to_string() })?;
+        let preprocessed = preprocessor::preprocess_file(shader_path, &self.defines)?;
+        self.compile(preprocessed, shader_type, Some(shader_path))
     }
 
-    pub unsafe fn compile_shader(mut self, shader_src: &str, shader_type: ShaderType) -> ShaderBuilder {
+    pub unsafe fn compile_shader(self, shader_src: &str, shader_type: ShaderType) -> Result<ShaderBuilder, ShaderError> {
+        let preprocessed = preprocessor::preprocess(shader_src, &self.defines)?;
+        self.compile(preprocessed, shader_type, None)
+    }
+
+    unsafe fn compile(mut self, preprocessed: PreprocessedSource, shader_type: ShaderType, path: Option<&str>) -> Result<ShaderBuilder, ShaderError> {
+        let c_str_shader = match c_string(&preprocessed.source, || format!("{:?} shader {}", shader_type, path.unwrap_or("from source"))) {
+            Ok(c_str_shader) => c_str_shader,
+            Err(e) => {
+                self.delete_shaders();
+                return Err(e);
+            }
+        };
         let shader = gl::CreateShader(shader_type.into());
-        let c_str_shader = CString::new(shader_src.as_bytes()).unwrap();
         gl::ShaderSource(shader, 1, &c_str_shader.as_ptr(), ptr::null());
         gl::CompileShader(shader);
 
-        if !self.check_shader_errors(shader) {
-            panic!("Shader failed to compile.");
+        let mut success = i32::from(gl::FALSE);
+        gl::GetShaderiv(shader, gl::COMPILE_STATUS, &mut success);
+        let mut log = ShaderLog::parse(&shader_info_log(shader));
+        let files: Vec<(Option<String>, &str)> = preprocessed.files.iter()
+            .map(|file| (file.path.clone(), file.source.as_str()))
+            .collect();
+        log.annotate(&files);
+
+        if success != i32::from(gl::TRUE) {
+            gl::DeleteShader(shader);
+            self.delete_shaders();
+            return Err(ShaderError::Compile { stage: shader_type, path: path.map(|p| p.to_string()), log });
+        }
+        // Drivers also log warnings for shaders that compile fine
+        if !log.messages.is_empty() {
+            self.diagnostics.push(Diagnostics { stage: Some(shader_type), path: path.map(|p| p.to_string()), log });
         }
 
         self.shaders.push(shader);
+        self.sources.push(ShaderSource {
+            shader_type,
+            path    : path.map(|p| p.to_string()),
+            source  : preprocessed.files[0].source.clone(),
+            defines : self.defines.clone(),
+            files   : preprocessed.files.iter()
+                .filter_map(|file| Some((file.path.clone()?, file.modified)))
+                .collect(),
+        });
 
-        self
+        Ok(self)
     }
 
-    unsafe fn check_shader_errors(&self, shader_id: u32) -> bool {
-        let mut success = i32::from(gl::FALSE);
-        let mut info_log = Vec::with_capacity(512);
-        info_log.set_len(512 - 1);
-        gl::GetShaderiv(shader_id, gl::COMPILE_STATUS, &mut success);
-        if success != i32::from(gl::TRUE) {
-            gl::GetShaderInfoLog(
-                shader_id,
-                512,
-                ptr::null_mut(),
-                info_log.as_mut_ptr() as *mut gl::types::GLchar,
-            );
-            println!("ERROR::Shader Compilation Failed!\n{}", String::from_utf8_lossy(&info_log));
-            return false;
+    // The shader objects are not needed once linked, or once the builder has failed
+    unsafe fn delete_shaders(&self) {
+        for &shader in &self.shaders {
+            gl::DeleteShader(shader);
         }
-        true
     }
 
-    unsafe fn check_linker_errors(&self) -> bool {
+    #[must_use = "The shader program is useless if not stored in a variable."]
+    pub unsafe fn link(self) -> Result<Shader, ShaderError> {
+        for &shader in &self.shaders {
+            gl::AttachShader(self.program.id(), shader);
+        }
+        gl::LinkProgram(self.program.id());
+
         let mut success = i32::from(gl::FALSE);
-        let mut info_log = Vec::with_capacity(512);
-        info_log.set_len(512 - 1);
-        gl::GetProgramiv(self.program_id, gl::LINK_STATUS, &mut success);
+        gl::GetProgramiv(self.program.id(), gl::LINK_STATUS, &mut success);
+        let log = ShaderLog::parse(&program_info_log(self.program.id()));
+        self.delete_shaders();
+
         if success != i32::from(gl::TRUE) {
-            gl::GetProgramInfoLog(
-                self.program_id,
-                512,
-                ptr::null_mut(),
-                info_log.as_mut_ptr() as *mut gl::types::GLchar,
-            );
-            println!("ERROR::SHADER::PROGRAM::COMPILATION_FAILED\n{}", String::from_utf8_lossy(&info_log));
-            return false;
+            return Err(ShaderError::Link { log });
+        }
+        let mut diagnostics = self.diagnostics;
+        if !log.messages.is_empty() {
+            diagnostics.push(Diagnostics { stage: None, path: None, log });
         }
-        true
+
+        let uniform_info = reflection::active_uniforms(self.program.id())?;
+        Ok(Shader {
+            attributes: reflection::active_attributes(self.program.id())?,
+            uniforms: UniformLocations::of_uniforms(&uniform_info),
+            uniform_info,
+            program: self.program,
+            diagnostics,
+            sources: self.sources,
+        })
     }
+}
 
-    #[must_use = "The shader program is useless if not stored in a variable."]
-    pub unsafe fn link(self) -> Shader {
-        for &shader in &self.shaders {
-            gl::AttachShader(self.program_id, shader);
-        }
-        gl::LinkProgram(self.program_id);
+// The whole info log of a shader, however long it is
+unsafe fn shader_info_log(shader: u32) -> String {
+    let mut length = 0;
+    gl::GetShaderiv(shader, gl::INFO_LOG_LENGTH, &mut length);
+    let mut info_log = vec![0u8; length.max(1) as usize];
+    let mut written = 0;
+    gl::GetShaderInfoLog(shader, info_log.len() as i32, &mut written, info_log.as_mut_ptr() as *mut gl::types::GLchar);
+    info_log.truncate(written.max(0) as usize);
+    String::from_utf8_lossy(&info_log).to_string()
+}
 
-        // todo:: use this to make safer abstraction
-        self.check_linker_errors();
+unsafe fn program_info_log(program: u32) -> String {
+    let mut length = 0;
+    gl::GetProgramiv(program, gl::INFO_LOG_LENGTH, &mut length);
+    let mut info_log = vec![0u8; length.max(1) as usize];
+    let mut written = 0;
+    gl::GetProgramInfoLog(program, info_log.len() as i32, &mut written, info_log.as_mut_ptr() as *mut gl::types::GLchar);
+    info_log.truncate(written.max(0) as usize);
+    String::from_utf8_lossy(&info_log).to_string()
+}
 
-        for &shader in &self.shaders {
-            gl::DeleteShader(shader);
-        }
 
-        Shader {
-            program_id: self.program_id
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mesa_nvidia_and_amd_logs_are_located() {
+        let log = ShaderLog::parse("\
+0:12(5): error: `foo' undeclared
+0(7) : warning C7050: \"bar\" might be used before being initialized
+ERROR: 0:3: 'vec5' : no matching overloaded function found
+ERROR: 1 compilation errors.  No code generated.
+");
+        let located: Vec<(Severity, Option<u32>, Option<u32>, &str)> = log.messages.iter()
+            .map(|m| (m.severity, m.file, m.line, m.text.as_str()))
+            .collect();
+        assert_eq!(located, [
+            (Severity::Error, Some(0), Some(12), "`foo' undeclared"),
+            (Severity::Warning, Some(0), Some(7), "\"bar\" might be used before being initialized"),
+            (Severity::Error, Some(0), Some(3), "'vec5' : no matching overloaded function found"),
+            (Severity::Error, None, None, "1 compilation errors.  No code generated."),
+        ]);
+        assert!(log.has_errors());
+    }
+
+    #[test]
+    fn messages_are_shown_next_to_their_source_line() {
+        let mut log = ShaderLog::parse("0:2(1): error: syntax error, unexpected '}'");
+        log.annotate(&[(Some("simple.frag".to_string()), "void main() {\n}}\n")]);
+        let error = ShaderError::Compile { stage: ShaderType::Fragment, path: Some("simple.frag".to_string()), log };
+        assert_eq!(error.to_string(), "\
+Failed to compile Fragment shader simple.frag:
+  simple.frag:2: Error: syntax error, unexpected '}'
+      | }}
+");
+    }
+
+    #[test]
+    fn wrong_stages_are_explained() {
+        let error = ShaderError::WrongStages { expected: vec![ShaderType::Compute], found: vec![ShaderType::Vertex, ShaderType::Fragment] };
+        assert_eq!(error.to_string(), "Expected a program built from [Compute] shaders, but it was built from [Vertex, Fragment]");
+    }
+
+    #[test]
+    fn nul_bytes_are_an_error() {
+        assert_eq!(c_string("u_model", || unreachable!()).unwrap().as_bytes(), b"u_model");
+        let error = c_string("void main() {}\0", || "Fragment shader from source".to_string()).unwrap_err();
+        assert_eq!(error.to_string(), "Fragment shader from source contains a NUL byte, which OpenGL cannot be given");
+    }
+
+    #[test]
+    fn edited_files_are_noticed() {
+        let path = std::env::temp_dir().join(format!("gloom-rs-reload-{}.frag", std::process::id()));
+        std::fs::write(&path, "void main() {}").unwrap();
+        let path = path.to_str().unwrap().to_string();
+        let source = ShaderSource {
+            shader_type : ShaderType::Fragment,
+            path        : Some(path.clone()),
+            source      : String::new(),
+            defines     : vec![],
+            files       : vec![(path.clone(), preprocessor::modification_time(&path))],
+        };
+        assert!(!source.has_changed());
+
+        let later = source.files[0].1.unwrap() + std::time::Duration::from_secs(5);
+        std::fs::File::options().write(true).open(&path).unwrap().set_modified(later).unwrap();
+        assert!(source.has_changed());
+    }
+
+    #[test]
+    fn unknown_uniforms_are_remembered() {
+        let uniforms = UniformLocations {
+            locations : [("u_model".to_string(), 3)].iter().cloned().collect(),
+            warned    : RefCell::default(),
+        };
+        assert_eq!(uniforms.get("u_model"), Some(3));
+        assert_eq!(uniforms.get("u_modle"), None);
+        assert_eq!(uniforms.get("u_modle"), None);
+        assert_eq!(uniforms.warned.borrow().len(), 1);
+    }
+
+    #[test]
+    fn link_logs_without_locations_are_kept() {
+        let log = ShaderLog::parse("error: vertex shader output `vertexTangent' is not read\n");
+        assert_eq!(log.messages[0].severity, Severity::Error);
+        assert_eq!(log.messages[0].file, None);
+        assert_eq!(log.messages[0].text, "vertex shader output `vertexTangent' is not read");
+    }
+
+    #[test]
+    fn compute_shaders_are_recognized() {
+        assert_eq!(ShaderType::from_ext(std::ffi::OsStr::new("comp")), Ok(ShaderType::Compute));
+        assert_eq!(Into::<gl::types::GLenum>::into(ShaderType::Compute), gl::COMPUTE_SHADER);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn extensions_that_are_not_utf8_are_unknown() {
+        use std::os::unix::ffi::OsStrExt;
+        assert!(ShaderType::from_ext(std::ffi::OsStr::from_bytes(b"fr\xffg")).is_err());
     }
 }
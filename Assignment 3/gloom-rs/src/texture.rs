@@ -0,0 +1,109 @@
+use crate::capabilities;
+use crate::gpu;
+
+// How texture coordinates outside of [0, 1] are handled
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug)]
+pub enum Wrap {
+    Repeat,
+    MirroredRepeat,
+    ClampToEdge,
+}
+
+// How texels are combined when the texture is drawn larger or smaller than it is
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug)]
+pub enum Filter {
+    Nearest,
+    Linear,
+}
+
+impl From<Wrap> for gl::types::GLenum {
+    fn from(wrap: Wrap) -> Self {
+        match wrap {
+            Wrap::Repeat         => { gl::REPEAT          },
+            Wrap::MirroredRepeat => { gl::MIRRORED_REPEAT },
+            Wrap::ClampToEdge    => { gl::CLAMP_TO_EDGE   },
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct TextureOptions {
+    pub wrap       : Wrap,
+    pub min_filter : Filter,
+    pub mag_filter : Filter,
+    pub mipmaps    : bool,
+    pub anisotropy : f32,   // Above 1 for sharper textures seen at an angle, if supported
+}
+
+impl Default for TextureOptions {
+    fn default() -> Self {
+        TextureOptions {
+            wrap       : Wrap::Repeat,
+            min_filter : Filter::Linear,
+            mag_filter : Filter::Linear,
+            mipmaps    : true,
+            anisotropy : 1.0,
+        }
+    }
+}
+
+pub struct Texture {
+    pub texture : gpu::Texture,
+    pub width   : u32,
+    pub height  : u32,
+}
+
+impl Texture {
+    // Loads a PNG or JPEG file into a 2D texture
+    pub unsafe fn load(path: &str, options: &TextureOptions) -> Result<Texture, String> {
+        // OpenGL expects the bottom row of the image first
+        let image = image::open(path)
+            .map_err(|e| format!("Failed to load texture {}: {}", path, e))?
+            .flipv()
+            .into_rgba8();
+        let (width, height) = image.dimensions();
+
+        let texture = gpu::Texture::new();
+        gl::BindTexture(gl::TEXTURE_2D, texture.id());
+
+        let wrap: gl::types::GLenum = options.wrap.into();
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, wrap as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, wrap as i32);
+
+        let min_filter = match (options.min_filter, options.mipmaps) {
+            (Filter::Nearest, false) => gl::NEAREST,
+            (Filter::Linear,  false) => gl::LINEAR,
+            (Filter::Nearest, true)  => gl::NEAREST_MIPMAP_LINEAR,
+            (Filter::Linear,  true)  => gl::LINEAR_MIPMAP_LINEAR,
+        };
+        let mag_filter = match options.mag_filter {
+            Filter::Nearest => gl::NEAREST,
+            Filter::Linear  => gl::LINEAR,
+        };
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, min_filter as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, mag_filter as i32);
+        if options.anisotropy > 1.0 {
+            gl::TexParameterf(gl::TEXTURE_2D, capabilities::TEXTURE_MAX_ANISOTROPY, options.anisotropy);
+        }
+
+        gl::TexImage2D(
+            gl::TEXTURE_2D,
+            0,
+            gl::RGBA8 as i32,
+            width as i32,
+            height as i32,
+            0,
+            gl::RGBA,
+            gl::UNSIGNED_BYTE,
+            image.as_raw().as_ptr() as *const std::ffi::c_void,
+        );
+        if options.mipmaps {
+            gl::GenerateMipmap(gl::TEXTURE_2D);
+        }
+        gl::BindTexture(gl::TEXTURE_2D, 0);
+
+        Ok(Texture { texture, width, height })
+    }
+}
@@ -0,0 +1,321 @@
+use std::{ collections::HashSet, mem, os::raw::c_void };
+
+use crate::gpu::Buffer;
+
+// A vertex format is described once, as a list of attributes, and all of them are packed
+// interleaved into a single buffer:
+//
+//     let layout = VertexLayout::new()
+//         .with("position", 0, 3, ComponentType::Float, false)
+//         .with("color", 1, 4, ComponentType::UnsignedByte, true);
+//     let vertices = VertexBuffer::builder(layout, 3)
+//         .attribute("position", &positions)
+//         .attribute("color", &colors)
+//         .build()?;
+//
+// The locations have to match the `layout(location = ...)` declarations of the vertex shader.
+
+// The type of every component of an attribute
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ComponentType {
+    Float,
+    Byte,
+    UnsignedByte,
+    Short,
+    UnsignedShort,
+    Int,
+    UnsignedInt,
+}
+
+impl ComponentType {
+    pub fn size(self) -> usize {
+        match self {
+            ComponentType::Byte  | ComponentType::UnsignedByte  => 1,
+            ComponentType::Short | ComponentType::UnsignedShort => 2,
+            ComponentType::Float | ComponentType::Int | ComponentType::UnsignedInt => 4,
+        }
+    }
+
+    pub fn is_integer(self) -> bool {
+        self != ComponentType::Float
+    }
+}
+
+impl From<ComponentType> for gl::types::GLenum {
+    fn from(component_type: ComponentType) -> Self {
+        match component_type {
+            ComponentType::Float         => { gl::FLOAT          },
+            ComponentType::Byte          => { gl::BYTE           },
+            ComponentType::UnsignedByte  => { gl::UNSIGNED_BYTE  },
+            ComponentType::Short         => { gl::SHORT          },
+            ComponentType::UnsignedShort => { gl::UNSIGNED_SHORT },
+            ComponentType::Int           => { gl::INT            },
+            ComponentType::UnsignedInt   => { gl::UNSIGNED_INT   },
+        }
+    }
+}
+
+// A Rust type that can be stored as a component of a vertex attribute
+pub trait Component: Copy {
+    const TYPE: ComponentType;
+    fn write(self, bytes: &mut Vec<u8>);
+}
+
+macro_rules! impl_component {
+    ($($t:ty => $variant:ident),*) => {
+        $(impl Component for $t {
+            const TYPE: ComponentType = ComponentType::$variant;
+            fn write(self, bytes: &mut Vec<u8>) {
+                bytes.extend_from_slice(&self.to_ne_bytes());
+            }
+        })*
+    };
+}
+
+impl_component!(f32 => Float, i8 => Byte, u8 => UnsignedByte, i16 => Short, u16 => UnsignedShort, i32 => Int, u32 => UnsignedInt);
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct VertexAttribute {
+    pub name           : String,
+    pub location       : u32,
+    pub components     : usize,          // 1 to 4
+    pub component_type : ComponentType,
+    pub normalized     : bool,           // Whether integers are mapped to [0, 1] or [-1, 1] floats
+    pub offset         : usize,          // Bytes from the start of the vertex
+}
+
+// Integer attributes that are not normalized reach the shader as integers, for things like bone
+// indices, so they must be declared as `int`/`ivecN`/`uvecN` there.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct VertexLayout {
+    attributes : Vec<VertexAttribute>,
+    stride     : usize,
+}
+
+impl VertexLayout {
+    pub fn new() -> VertexLayout {
+        VertexLayout::default()
+    }
+
+    // Adds an attribute after the previous ones. Every attribute starts on a 4 byte boundary, as
+    // some drivers are slow otherwise.
+    pub fn with(mut self, name: &str, location: u32, components: usize, component_type: ComponentType, normalized: bool) -> VertexLayout {
+        assert!((1..=4).contains(&components), "Attribute {} must have 1 to 4 components, not {}", name, components);
+        assert!(self.get(name).is_none(), "Attribute {} is already part of the layout", name);
+        assert!(self.attributes.iter().all(|a| a.location != location), "Location {} is already used by another attribute", location);
+
+        let offset = self.stride;
+        self.stride = align_to_4(offset + components * component_type.size());
+        self.attributes.push(VertexAttribute {
+            name: name.to_string(),
+            location,
+            components,
+            component_type,
+            normalized,
+            offset,
+        });
+        self
+    }
+
+    pub fn attributes(&self) -> &[VertexAttribute] {
+        &self.attributes
+    }
+
+    pub fn get(&self, name: &str) -> Option<&VertexAttribute> {
+        self.attributes.iter().find(|a| a.name == name)
+    }
+
+    // Bytes from the start of one vertex to the next
+    #[allow(dead_code)]
+    pub fn stride(&self) -> usize {
+        self.stride
+    }
+
+    // Points every attribute at the buffer currently bound to GL_ARRAY_BUFFER. The VAO the
+    // attributes belong to has to be bound.
+    pub unsafe fn enable(&self) {
+        for attribute in &self.attributes {
+            let pointer = attribute.offset as *const c_void;
+            gl::EnableVertexAttribArray(attribute.location);
+            if attribute.component_type.is_integer() && !attribute.normalized {
+                gl::VertexAttribIPointer(
+                    attribute.location,
+                    attribute.components as i32,
+                    attribute.component_type.into(),
+                    self.stride as i32,
+                    pointer,
+                );
+            } else {
+                gl::VertexAttribPointer(
+                    attribute.location,
+                    attribute.components as i32,
+                    attribute.component_type.into(),
+                    attribute.normalized as gl::types::GLboolean,
+                    self.stride as i32,
+                    pointer,
+                );
+            }
+        }
+    }
+}
+
+fn align_to_4(bytes: usize) -> usize {
+    (bytes + 3) & !3
+}
+
+// Vertex data packed according to a layout, ready to be uploaded
+#[allow(dead_code)]
+#[derive(Debug)]
+pub struct VertexBuffer {
+    pub layout       : VertexLayout,
+    pub vertex_count : usize,
+    pub data         : Vec<u8>,
+}
+
+pub struct VertexBufferBuilder {
+    layout       : VertexLayout,
+    vertex_count : usize,
+    columns      : Vec<Option<Vec<u8>>>,  // The bytes of each attribute, in layout order
+    problems     : Vec<String>,
+    reported     : HashSet<usize>,        // Attributes already in the problems, by layout index
+}
+
+impl VertexBuffer {
+    pub fn builder(layout: VertexLayout, vertex_count: usize) -> VertexBufferBuilder {
+        let columns = vec![None; layout.attributes.len()];
+        VertexBufferBuilder { layout, vertex_count, columns, problems: vec![], reported: HashSet::new() }
+    }
+
+    // Creates a VBO holding the vertices, and sets up the attributes of the bound VAO to read
+    // from it. The VBO has to be kept alive for as long as the VAO is used.
+    pub unsafe fn upload(&self) -> Buffer {
+        let vbo = Buffer::with_data(gl::ARRAY_BUFFER, &self.data);
+        self.layout.enable();
+        vbo
+    }
+}
+
+impl VertexBufferBuilder {
+    // Gives the values of one attribute for every vertex, one after another. The Rust type has to
+    // match the component type of the attribute.
+    pub fn attribute<T: Component>(mut self, name: &str, values: &[T]) -> VertexBufferBuilder {
+        let index = match self.layout.attributes.iter().position(|a| a.name == name) {
+            Some(index) => index,
+            None => {
+                self.problems.push(format!("the layout has no attribute named {}", name));
+                return self;
+            }
+        };
+        let attribute = &self.layout.attributes[index];
+        self.reported.insert(index);
+        if T::TYPE != attribute.component_type {
+            self.problems.push(format!("{} holds {:?} components, but was given {:?}", name, attribute.component_type, T::TYPE));
+        } else if values.len() != self.vertex_count * attribute.components {
+            self.problems.push(format!("{} has {} components for {} vertices, expected {}",
+                name, values.len(), self.vertex_count, self.vertex_count * attribute.components));
+        } else {
+            let mut bytes = Vec::with_capacity(mem::size_of_val(values));
+            for &value in values {
+                value.write(&mut bytes);
+            }
+            self.columns[index] = Some(bytes);
+        }
+        self
+    }
+
+    pub fn build(self) -> Result<VertexBuffer, String> {
+        let mut problems = self.problems;
+        for (index, (attribute, column)) in self.layout.attributes.iter().zip(&self.columns).enumerate() {
+            if column.is_none() && !self.reported.contains(&index) {
+                problems.push(format!("no values were given for {}", attribute.name));
+            }
+        }
+        if !problems.is_empty() {
+            return Err(problems.join("; "));
+        }
+
+        let stride = self.layout.stride;
+        let mut data = vec![0u8; stride * self.vertex_count];
+        for (attribute, column) in self.layout.attributes.iter().zip(self.columns) {
+            let column = column.unwrap();
+            let size = attribute.components * attribute.component_type.size();
+            for (vertex, values) in column.chunks_exact(size).enumerate() {
+                let start = vertex * stride + attribute.offset;
+                data[start..start + size].copy_from_slice(values);
+            }
+        }
+        Ok(VertexBuffer { layout: self.layout, vertex_count: self.vertex_count, data })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryInto;
+
+    fn layout() -> VertexLayout {
+        VertexLayout::new()
+            .with("position", 0, 3, ComponentType::Float, false)
+            .with("color", 1, 3, ComponentType::UnsignedByte, true)
+            .with("weight", 2, 1, ComponentType::Float, false)
+    }
+
+    #[test]
+    fn attributes_are_aligned_one_after_another() {
+        let layout = layout();
+        assert_eq!(layout.get("position").unwrap().offset, 0);
+        assert_eq!(layout.get("color").unwrap().offset, 12);
+        // The three color bytes are padded to four
+        assert_eq!(layout.get("weight").unwrap().offset, 16);
+        assert_eq!(layout.stride(), 20);
+    }
+
+    #[test]
+    fn vertices_are_interleaved() {
+        let buffer = VertexBuffer::builder(layout(), 2)
+            .attribute("position", &[1.0f32, 2.0, 3.0,  4.0, 5.0, 6.0])
+            .attribute("color", &[255u8, 0, 0,  0, 255, 0])
+            .attribute("weight", &[0.5f32, 0.25])
+            .build()
+            .unwrap();
+        assert_eq!(buffer.data.len(), 40);
+
+        let float_at = |offset: usize| f32::from_ne_bytes(buffer.data[offset..offset + 4].try_into().unwrap());
+        assert_eq!(float_at(0), 1.0);
+        assert_eq!(&buffer.data[12..15], &[255, 0, 0]);
+        assert_eq!(float_at(16), 0.5);
+        assert_eq!(float_at(20), 4.0);
+        assert_eq!(&buffer.data[32..35], &[0, 255, 0]);
+        assert_eq!(float_at(36), 0.25);
+    }
+
+    #[test]
+    fn mistakes_are_collected() {
+        let error = VertexBuffer::builder(layout(), 2)
+            .attribute("position", &[1.0f32, 2.0, 3.0])
+            .attribute("color", &[1.0f32; 6])
+            .attribute("uv", &[0.0f32; 4])
+            .build()
+            .unwrap_err();
+        assert!(error.contains("position has 3 components for 2 vertices, expected 6"), "{}", error);
+        assert!(error.contains("color holds UnsignedByte components, but was given Float"), "{}", error);
+        assert!(error.contains("no attribute named uv"), "{}", error);
+        assert!(error.contains("no values were given for weight"), "{}", error);
+    }
+
+    #[test]
+    fn missing_attributes_are_told_apart_from_similar_names() {
+        let layout = VertexLayout::new()
+            .with("tex", 0, 2, ComponentType::Float, false)
+            .with("texcoord", 1, 2, ComponentType::Float, false);
+        let error = VertexBuffer::builder(layout, 1)
+            .attribute("texcoord", &[0.0f32])
+            .build()
+            .unwrap_err();
+        assert!(error.contains("texcoord has 1 components for 1 vertices, expected 2"), "{}", error);
+        assert!(error.contains("no values were given for tex"), "{}", error);
+        assert!(!error.contains("no values were given for texcoord"), "{}", error);
+    }
+}
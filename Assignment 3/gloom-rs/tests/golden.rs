@@ -0,0 +1,139 @@
+// Renders the scenes of assignments 1 and 2 headless and compares them against the reference
+// images in tests/golden. Rendering differs a little between drivers, so the images only have to be
+// close: the root mean square difference of the color channels must stay below TOLERANCE.
+//
+// When a scene does not match, the rendered image and a picture of the difference are saved
+// next to each other in the target folder. Run with GLOOM_UPDATE_GOLDEN=1 to replace the
+// references with what is rendered, after checking that it looks right.
+//
+// Rendering headless needs libEGL, so the scenes are skipped where it cannot be loaded, and never
+// rendered on Windows. The lunar scene is left out, as its models are not checked in.
+#![cfg_attr(not(unix), allow(dead_code, unused_imports))]
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use image::{Rgb, RgbImage};
+
+const WIDTH: u32 = 320;
+const HEIGHT: u32 = 240;
+
+// Rendered at 10 frames per second, so the last frame is 3 seconds in
+const FRAMES: u32 = 31;
+const FPS: u32 = 10;
+
+// Out of 255. Differences in rasterization make edges a pixel off here and there, which stays
+// well below this, while a missing object or a wrong color does not.
+const TOLERANCE: f64 = 2.0;
+
+fn golden_path(scene: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden").join(format!("{}.png", scene))
+}
+
+fn output_path(file: &str) -> PathBuf {
+    Path::new(env!("CARGO_TARGET_TMPDIR")).join("golden").join(file)
+}
+
+fn render(scene: &str) -> RgbImage {
+    let path = output_path(&format!("{}-rendered.png", scene));
+    let output = Command::new(env!("CARGO_BIN_EXE_gloom-rs"))
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .args(["--headless", &format!("{}x{}", WIDTH, HEIGHT)])
+        .args(["--scene", scene])
+        .args(["--frames", &FRAMES.to_string(), "--fps", &FPS.to_string()])
+        .arg("--screenshot").arg(&path)
+        .output()
+        .expect("Failed to run gloom-rs");
+    assert!(output.status.success(), "Rendering {} failed:\n{}{}",
+        scene,
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr));
+
+    image::open(&path).expect("Failed to open the rendered image").to_rgb8()
+}
+
+// The root mean square difference of all color channels
+fn rmse(a: &RgbImage, b: &RgbImage) -> f64 {
+    let squares: f64 = a.as_raw().iter().zip(b.as_raw())
+        .map(|(&a, &b)| (a as f64 - b as f64).powi(2))
+        .sum();
+    (squares / a.as_raw().len() as f64).sqrt()
+}
+
+// Black where the images agree, brighter the more they differ, so that small differences show
+fn difference(a: &RgbImage, b: &RgbImage) -> RgbImage {
+    RgbImage::from_fn(a.width(), a.height(), |x, y| {
+        let (a, b) = (a.get_pixel(x, y), b.get_pixel(x, y));
+        Rgb([0, 1, 2].map(|c| (a[c] as i32 - b[c] as i32).unsigned_abs().saturating_mul(8).min(255) as u8))
+    })
+}
+
+// Whether the binary will be able to render headless, as far as can be told from here
+#[cfg(unix)]
+fn egl_available() -> bool {
+    ["libEGL.so.1", "libEGL.so"].iter().any(|name| {
+        let name = std::ffi::CString::new(*name).unwrap();
+        let library = unsafe { libc::dlopen(name.as_ptr(), libc::RTLD_LAZY) };
+        if !library.is_null() {
+            unsafe { libc::dlclose(library) };
+        }
+        !library.is_null()
+    })
+}
+
+fn check(scene: &str) {
+    #[cfg(unix)]
+    if !egl_available() {
+        println!("Skipping {}, as libEGL cannot be loaded", scene);
+        return;
+    }
+    let rendered = render(scene);
+    let golden_path = golden_path(scene);
+
+    if std::env::var_os("GLOOM_UPDATE_GOLDEN").is_some() {
+        std::fs::create_dir_all(golden_path.parent().unwrap()).unwrap();
+        rendered.save(&golden_path).unwrap();
+        println!("Updated {}", golden_path.display());
+        return;
+    }
+    let golden = match image::open(&golden_path) {
+        Ok(golden) => golden.to_rgb8(),
+        Err(e) => panic!("Failed to open the reference image {} ({}). Run the tests with \
+            GLOOM_UPDATE_GOLDEN=1 to render it, and check that it looks right.", golden_path.display(), e),
+    };
+    assert_eq!(rendered.dimensions(), golden.dimensions(), "{} was rendered at the wrong size", scene);
+
+    let error = rmse(&rendered, &golden);
+    if error > TOLERANCE {
+        let actual_path = output_path(&format!("{}-actual.png", scene));
+        let diff_path = output_path(&format!("{}-diff.png", scene));
+        rendered.save(&actual_path).unwrap();
+        difference(&rendered, &golden).save(&diff_path).unwrap();
+        panic!("{} differs from {} by {:.2}, more than {}. Compare {} with it, and see {} for where.",
+            scene, golden_path.display(), error, TOLERANCE, actual_path.display(), diff_path.display());
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn assignment_1_circle() {
+    check("circle");
+}
+
+#[cfg(unix)]
+#[test]
+fn assignment_2_billboards() {
+    check("billboards");
+}
+
+#[test]
+fn identical_images_do_not_differ() {
+    let image = RgbImage::from_fn(4, 4, |x, y| Rgb([x as u8 * 60, y as u8 * 60, 200]));
+    assert_eq!(rmse(&image, &image), 0.0);
+    assert_eq!(difference(&image, &image), RgbImage::new(4, 4));
+
+    let mut brighter = image.clone();
+    brighter.pixels_mut().for_each(|pixel| pixel[2] = 203);
+    assert_eq!(rmse(&image, &brighter), 3.0_f64.sqrt());
+    assert_eq!(difference(&image, &brighter).get_pixel(0, 0), &Rgb([0, 0, 24]));
+}